@@ -8,6 +8,7 @@ use futures::stream::Peekable;
 
 use super::layer::*;
 use crate::storage::*;
+use crate::structure::util::calculate_width;
 use crate::structure::*;
 
 use std::collections::BTreeSet;
@@ -534,16 +535,34 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
     /// Create the builder from the given files.
     pub fn from_files(files: &BaseLayerFiles<F>) -> Self {
         let node_dictionary_builder = PfcDictFileBuilder::new(
-            files.node_dictionary_files.blocks_file.open_write(),
-            files.node_dictionary_files.offsets_file.open_write(),
+            files
+                .node_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .node_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
         let predicate_dictionary_builder = PfcDictFileBuilder::new(
-            files.predicate_dictionary_files.blocks_file.open_write(),
-            files.predicate_dictionary_files.offsets_file.open_write(),
+            files
+                .predicate_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .predicate_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
         let value_dictionary_builder = PfcDictFileBuilder::new(
-            files.value_dictionary_files.blocks_file.open_write(),
-            files.value_dictionary_files.offsets_file.open_write(),
+            files
+                .value_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .value_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
 
         BaseLayerFileBuilder {
@@ -556,7 +575,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add a node string.
     ///
-    /// Panics if the given node string is not a lexical successor of the previous node string.
+    /// Fails with an `InvalidInput` error if the given node string is not a lexical successor of the previous node string.
     pub fn add_node(self, node: &str) -> impl Future<Item = (u64, Self), Error = std::io::Error> {
         let BaseLayerFileBuilder {
             files,
@@ -584,7 +603,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add a predicate string.
     ///
-    /// Panics if the given predicate string is not a lexical successor of the previous node string.
+    /// Fails with an `InvalidInput` error if the given predicate string is not a lexical successor of the previous predicate string.
     pub fn add_predicate(
         self,
         predicate: &str,
@@ -615,7 +634,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add a value string.
     ///
-    /// Panics if the given value string is not a lexical successor of the previous value string.
+    /// Fails with an `InvalidInput` error if the given value string is not a lexical successor of the previous value string.
     pub fn add_value(self, value: &str) -> impl Future<Item = (u64, Self), Error = std::io::Error> {
         let BaseLayerFileBuilder {
             files,
@@ -643,7 +662,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add nodes from an iterable.
     ///
-    /// Panics if the nodes are not in lexical order, or if previous added nodes are a lexical succesor of any of these nodes.
+    /// Fails with an `InvalidInput` error if the nodes are not in lexical order, or if previous added nodes are a lexical succesor of any of these nodes.
     pub fn add_nodes<I: 'static + IntoIterator<Item = String> + Send + Sync>(
         self,
         nodes: I,
@@ -665,7 +684,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add predicates from an iterable.
     ///
-    /// Panics if the predicates are not in lexical order, or if previous added predicates are a lexical succesor of any of these predicates.
+    /// Fails with an `InvalidInput` error if the predicates are not in lexical order, or if previous added predicates are a lexical succesor of any of these predicates.
     pub fn add_predicates<I: 'static + IntoIterator<Item = String> + Send + Sync>(
         self,
         predicates: I,
@@ -687,7 +706,7 @@ impl<F: 'static + FileLoad + FileStore + Clone> BaseLayerFileBuilder<F> {
 
     /// Add values from an iterable.
     ///
-    /// Panics if the values are not in lexical order, or if previous added values are a lexical succesor of any of these values.
+    /// Fails with an `InvalidInput` error if the values are not in lexical order, or if previous added values are a lexical succesor of any of these values.
     pub fn add_values<I: 'static + IntoIterator<Item = String> + Send + Sync>(
         self,
         values: I,
@@ -788,8 +807,8 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
         num_predicates: usize,
         num_values: usize,
     ) -> Self {
-        let s_p_width = ((num_predicates + 1) as f32).log2().ceil() as u8;
-        let sp_o_width = ((num_nodes + num_values + 1) as f32).log2().ceil() as u8;
+        let s_p_width = calculate_width(num_predicates as u64);
+        let sp_o_width = calculate_width((num_nodes + num_values) as u64);
         let f = files.clone();
         let s_p_adjacency_list_builder = AdjacencyListBuilder::new(
             files.s_p_adjacency_list_files.bitindex_files.bits_file,
@@ -797,13 +816,16 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
                 .s_p_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .s_p_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.s_p_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .s_p_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             s_p_width,
         );
 
@@ -813,13 +835,16 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
                 .sp_o_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .sp_o_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.sp_o_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .sp_o_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             sp_o_width,
         );
 
@@ -930,19 +955,19 @@ impl<F: 'static + FileLoad + FileStore> BaseLayerFileBuilderPhase2<F> {
             for pad_object in (*greatest_left + 1)..(object_count as u64) + 1 {
                 tuples.insert((pad_object, 0));
             }
-            let width = ((greatest_right + 1) as f32).log2().ceil() as u8;
+            let width = calculate_width(greatest_right);
 
             let o_ps_adjacency_list_builder = AdjacencyListBuilder::new(
                 o_ps_adjacency_list_files.bitindex_files.bits_file,
                 o_ps_adjacency_list_files
                     .bitindex_files
                     .blocks_file
-                    .open_write(),
+                    .open_write_truncate(),
                 o_ps_adjacency_list_files
                     .bitindex_files
                     .sblocks_file
-                    .open_write(),
-                o_ps_adjacency_list_files.nums_file.open_write(),
+                    .open_write_truncate(),
+                o_ps_adjacency_list_files.nums_file.open_write_truncate(),
                 width,
             );
 
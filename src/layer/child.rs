@@ -5,6 +5,7 @@
 //! this layer needs for its additions.
 use super::layer::*;
 use crate::storage::*;
+use crate::structure::util::calculate_width;
 use crate::structure::*;
 use futures::future;
 use futures::prelude::*;
@@ -716,16 +717,34 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
     /// Create the builder from the given files.
     pub fn from_files(parent: Arc<dyn Layer>, files: &ChildLayerFiles<F>) -> Self {
         let node_dictionary_builder = PfcDictFileBuilder::new(
-            files.node_dictionary_files.blocks_file.open_write(),
-            files.node_dictionary_files.offsets_file.open_write(),
+            files
+                .node_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .node_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
         let predicate_dictionary_builder = PfcDictFileBuilder::new(
-            files.predicate_dictionary_files.blocks_file.open_write(),
-            files.predicate_dictionary_files.offsets_file.open_write(),
+            files
+                .predicate_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .predicate_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
         let value_dictionary_builder = PfcDictFileBuilder::new(
-            files.value_dictionary_files.blocks_file.open_write(),
-            files.value_dictionary_files.offsets_file.open_write(),
+            files
+                .value_dictionary_files
+                .blocks_file
+                .open_write_truncate(),
+            files
+                .value_dictionary_files
+                .offsets_file
+                .open_write_truncate(),
         );
 
         Self {
@@ -741,8 +760,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
     /// Add a node string.
     ///
     /// Does nothing if the node already exists in the paretn, and
-    /// panics if the given node string is not a lexical successor of
-    /// the previous node string.
+    /// fails with an `InvalidInput` error if the given node string is
+    /// not a lexical successor of the previous node string.
     pub fn add_node(
         self,
         node: &str,
@@ -780,8 +799,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
     /// Add a predicate string.
     ///
     /// Does nothing if the predicate already exists in the paretn, and
-    /// panics if the given predicate string is not a lexical successor of
-    /// the previous predicate string.
+    /// fails with an `InvalidInput` error if the given predicate string is
+    /// not a lexical successor of the previous predicate string.
     pub fn add_predicate(
         self,
         predicate: &str,
@@ -820,8 +839,8 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
     /// Add a value string.
     ///
     /// Does nothing if the value already exists in the paretn, and
-    /// panics if the given value string is not a lexical successor of
-    /// the previous value string.
+    /// fails with an `InvalidInput` error if the given value string is
+    /// not a lexical successor of the previous value string.
     pub fn add_value(
         self,
         value: &str,
@@ -858,9 +877,9 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
 
     /// Add nodes from an iterable.
     ///
-    /// Panics if the nodes are not in lexical order, or if previous
-    /// added nodes are a lexical succesor of any of these
-    /// nodes. Skips any nodes that are already part of the base
+    /// Fails with an `InvalidInput` error if the nodes are not in
+    /// lexical order, or if previous added nodes are a lexical
+    /// succesor of any of these nodes. Skips any nodes that are already part of the base
     /// layer.
     pub fn add_nodes<I: 'static + IntoIterator<Item = String>>(
         self,
@@ -880,9 +899,9 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
 
     /// Add predicates from an iterable.
     ///
-    /// Panics if the predicates are not in lexical order, or if
-    /// previous added predicates are a lexical succesor of any of
-    /// these predicates. Skips any predicates that are already part
+    /// Fails with an `InvalidInput` error if the predicates are not in
+    /// lexical order, or if previous added predicates are a lexical
+    /// succesor of any of these predicates. Skips any predicates that are already part
     /// of the base layer.
     pub fn add_predicates<I: 'static + IntoIterator<Item = String>>(
         self,
@@ -902,9 +921,9 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
 
     /// Add values from an iterable.
     ///
-    /// Panics if the values are not in lexical order, or if previous
-    /// added values are a lexical succesor of any of these
-    /// values. Skips any nodes that are already part of the base
+    /// Fails with an `InvalidInput` error if the values are not in
+    /// lexical order, or if previous added values are a lexical
+    /// succesor of any of these values. Skips any nodes that are already part of the base
     /// layer.
     pub fn add_values<I: 'static + IntoIterator<Item = String>>(
         self,
@@ -1021,14 +1040,10 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         let pos_subjects = Vec::new();
         let neg_subjects = Vec::new();
         let parent_counts = parent.all_counts();
-        let s_p_width = ((parent_counts.predicate_count + num_predicates + 1) as f32)
-            .log2()
-            .ceil() as u8;
-        let sp_o_width =
-            ((parent_counts.node_count + parent_counts.value_count + num_nodes + num_values + 1)
-                as f32)
-                .log2()
-                .ceil() as u8;
+        let s_p_width = calculate_width((parent_counts.predicate_count + num_predicates) as u64);
+        let sp_o_width = calculate_width(
+            (parent_counts.node_count + parent_counts.value_count + num_nodes + num_values) as u64,
+        );
 
         let f = files.clone();
 
@@ -1038,13 +1053,16 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
                 .pos_s_p_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .pos_s_p_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.pos_s_p_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .pos_s_p_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             s_p_width,
         );
 
@@ -1054,13 +1072,16 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
                 .pos_sp_o_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .pos_sp_o_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.pos_sp_o_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .pos_sp_o_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             sp_o_width,
         );
 
@@ -1070,13 +1091,16 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
                 .neg_s_p_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .neg_s_p_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.neg_s_p_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .neg_s_p_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             s_p_width,
         );
 
@@ -1086,13 +1110,16 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
                 .neg_sp_o_adjacency_list_files
                 .bitindex_files
                 .blocks_file
-                .open_write(),
+                .open_write_truncate(),
             files
                 .neg_sp_o_adjacency_list_files
                 .bitindex_files
                 .sblocks_file
-                .open_write(),
-            files.neg_sp_o_adjacency_list_files.nums_file.open_write(),
+                .open_write_truncate(),
+            files
+                .neg_sp_o_adjacency_list_files
+                .nums_file
+                .open_write_truncate(),
             sp_o_width,
         );
 
@@ -1341,11 +1368,11 @@ impl<F: 'static + FileLoad + FileStore + Clone + Send + Sync> ChildLayerFileBuil
         let pos_subjects_width = 1 + (max_pos_subject as f32).log2().ceil() as u8;
         let neg_subjects_width = 1 + (max_neg_subject as f32).log2().ceil() as u8;
         let pos_subjects_logarray_builder = LogArrayFileBuilder::new(
-            self.files.pos_subjects_file.open_write(),
+            self.files.pos_subjects_file.open_write_truncate(),
             pos_subjects_width,
         );
         let neg_subjects_logarray_builder = LogArrayFileBuilder::new(
-            self.files.neg_subjects_file.open_write(),
+            self.files.neg_subjects_file.open_write_truncate(),
             neg_subjects_width,
         );
 
@@ -1438,18 +1465,18 @@ fn build_object_index<F: 'static + FileLoad + FileStore>(
         )
         .and_then(move |(pairs, objects, greatest_sp)| {
             let greatest_object = objects.iter().next_back().unwrap_or(&0);
-            let objects_width = ((*greatest_object + 1) as f32).log2().ceil() as u8;
-            let aj_width = ((greatest_sp + 1) as f32).log2().ceil() as u8;
+            let objects_width = calculate_width(*greatest_object);
+            let aj_width = calculate_width(greatest_sp);
 
             let o_ps_adjacency_list_builder = AdjacencyListBuilder::new(
                 o_ps_files.bitindex_files.bits_file,
-                o_ps_files.bitindex_files.blocks_file.open_write(),
-                o_ps_files.bitindex_files.sblocks_file.open_write(),
-                o_ps_files.nums_file.open_write(),
+                o_ps_files.bitindex_files.blocks_file.open_write_truncate(),
+                o_ps_files.bitindex_files.sblocks_file.open_write_truncate(),
+                o_ps_files.nums_file.open_write_truncate(),
                 aj_width,
             );
             let objects_builder =
-                LogArrayFileBuilder::new(objects_file.open_write(), objects_width);
+                LogArrayFileBuilder::new(objects_file.open_write_truncate(), objects_width);
 
             let compressed_pairs = pairs
                 .into_iter()
@@ -157,3 +157,72 @@ pub const FILENAMES: Filenames = Filenames {
 
     parent: "parent.hex",
 };
+
+/// The files that make up a base layer, in the order the layer store loads them.
+pub const BASE_LAYER_FILES: [&str; 21] = [
+    FILENAMES.node_dictionary_blocks,
+    FILENAMES.node_dictionary_offsets,
+    FILENAMES.predicate_dictionary_blocks,
+    FILENAMES.predicate_dictionary_offsets,
+    FILENAMES.value_dictionary_blocks,
+    FILENAMES.value_dictionary_offsets,
+    FILENAMES.base_s_p_adjacency_list_bits,
+    FILENAMES.base_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.base_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.base_s_p_adjacency_list_nums,
+    FILENAMES.base_sp_o_adjacency_list_bits,
+    FILENAMES.base_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.base_sp_o_adjacency_list_bit_index_sblocks,
+    FILENAMES.base_sp_o_adjacency_list_nums,
+    FILENAMES.base_o_ps_adjacency_list_bits,
+    FILENAMES.base_o_ps_adjacency_list_bit_index_blocks,
+    FILENAMES.base_o_ps_adjacency_list_bit_index_sblocks,
+    FILENAMES.base_o_ps_adjacency_list_nums,
+    FILENAMES.base_predicate_wavelet_tree_bits,
+    FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
+    FILENAMES.base_predicate_wavelet_tree_bit_index_sblocks,
+];
+
+/// The files that make up a child layer, in the order the layer store loads them.
+pub const CHILD_LAYER_FILES: [&str; 40] = [
+    FILENAMES.node_dictionary_blocks,
+    FILENAMES.node_dictionary_offsets,
+    FILENAMES.predicate_dictionary_blocks,
+    FILENAMES.predicate_dictionary_offsets,
+    FILENAMES.value_dictionary_blocks,
+    FILENAMES.value_dictionary_offsets,
+    FILENAMES.pos_subjects,
+    FILENAMES.pos_objects,
+    FILENAMES.neg_subjects,
+    FILENAMES.neg_objects,
+    FILENAMES.pos_s_p_adjacency_list_bits,
+    FILENAMES.pos_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.pos_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.pos_s_p_adjacency_list_nums,
+    FILENAMES.pos_sp_o_adjacency_list_bits,
+    FILENAMES.pos_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.pos_sp_o_adjacency_list_bit_index_sblocks,
+    FILENAMES.pos_sp_o_adjacency_list_nums,
+    FILENAMES.pos_o_ps_adjacency_list_bits,
+    FILENAMES.pos_o_ps_adjacency_list_bit_index_blocks,
+    FILENAMES.pos_o_ps_adjacency_list_bit_index_sblocks,
+    FILENAMES.pos_o_ps_adjacency_list_nums,
+    FILENAMES.neg_s_p_adjacency_list_bits,
+    FILENAMES.neg_s_p_adjacency_list_bit_index_blocks,
+    FILENAMES.neg_s_p_adjacency_list_bit_index_sblocks,
+    FILENAMES.neg_s_p_adjacency_list_nums,
+    FILENAMES.neg_sp_o_adjacency_list_bits,
+    FILENAMES.neg_sp_o_adjacency_list_bit_index_blocks,
+    FILENAMES.neg_sp_o_adjacency_list_bit_index_sblocks,
+    FILENAMES.neg_sp_o_adjacency_list_nums,
+    FILENAMES.neg_o_ps_adjacency_list_bits,
+    FILENAMES.neg_o_ps_adjacency_list_bit_index_blocks,
+    FILENAMES.neg_o_ps_adjacency_list_bit_index_sblocks,
+    FILENAMES.neg_o_ps_adjacency_list_nums,
+    FILENAMES.pos_predicate_wavelet_tree_bits,
+    FILENAMES.pos_predicate_wavelet_tree_bit_index_blocks,
+    FILENAMES.pos_predicate_wavelet_tree_bit_index_sblocks,
+    FILENAMES.neg_predicate_wavelet_tree_bits,
+    FILENAMES.neg_predicate_wavelet_tree_bit_index_blocks,
+    FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
+];
@@ -3,11 +3,12 @@ use futures::prelude::*;
 use locking::*;
 use memmap::*;
 use std::io::{self, Seek, SeekFrom};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tokio::fs::{self, *};
 use tokio::prelude::*;
 
+use super::consts::{BASE_LAYER_FILES, CHILD_LAYER_FILES, FILENAMES};
 use super::*;
 
 const PREFIX_DIR_SIZE: usize = 3;
@@ -102,6 +103,118 @@ impl FileStore for FileBackedStore {
 
         File::from_std(file)
     }
+
+    fn open_write_truncate(&self) -> File {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).write(true).create(true).truncate(true);
+        let file = options.open(&self.path).unwrap();
+
+        File::from_std(file)
+    }
+
+    fn open_write_append(&self) -> File {
+        let mut options = std::fs::OpenOptions::new();
+        options.read(true).append(true).create(true);
+        let file = options.open(&self.path).unwrap();
+
+        File::from_std(file)
+    }
+}
+
+/// A temporary file that is removed once the last store referring to it is dropped.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // removing may fail (for example, because someone else already
+        // removed it), but there is nothing useful to do about that here.
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A file store backed by a temporary file.
+///
+/// This behaves like a `FileBackedStore` over a file in the system's
+/// temporary directory, so large files get memory mapped on read
+/// instead of being kept on the heap like in a `MemoryBackedStore`.
+/// The file is removed when the last clone of this store is
+/// dropped. Maps that are still alive at that point remain valid.
+///
+/// The file is not anonymous. It is named `terminus-store-*.tmp`, and
+/// is only removed on drop, so it is left behind if the process
+/// crashes. Every `open_*` call reopens the file by its path, and
+/// panics if the file has been removed by someone else in the
+/// meantime (for example, by a temp directory cleaner).
+#[derive(Clone)]
+pub struct TempMmapStore {
+    file: Arc<TempFile>,
+    store: FileBackedStore,
+}
+
+impl TempMmapStore {
+    /// Creates a new, empty temporary file to back this store.
+    pub fn new() -> io::Result<TempMmapStore> {
+        let dir = std::env::temp_dir();
+        loop {
+            let name: u64 = rand::random();
+            let path = dir.join(format!("terminus-store-{:016x}.tmp", name));
+            let result = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .open(&path);
+
+            match result {
+                Ok(_) => {
+                    return Ok(TempMmapStore {
+                        file: Arc::new(TempFile { path: path.clone() }),
+                        store: FileBackedStore::new(path),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the path of the temporary file backing this store.
+    pub fn path(&self) -> &std::path::Path {
+        &self.file.path
+    }
+}
+
+impl FileLoad for TempMmapStore {
+    type Read = File;
+    type Map = SharedMmap;
+
+    fn size(&self) -> usize {
+        self.store.size()
+    }
+
+    fn open_read_from(&self, offset: usize) -> File {
+        self.store.open_read_from(offset)
+    }
+
+    fn map(&self) -> Box<dyn Future<Item = SharedMmap, Error = std::io::Error> + Send> {
+        self.store.map()
+    }
+}
+
+impl FileStore for TempMmapStore {
+    type Write = File;
+
+    fn open_write_from(&self, offset: usize) -> File {
+        self.store.open_write_from(offset)
+    }
+
+    fn open_write_truncate(&self) -> File {
+        self.store.open_write_truncate()
+    }
+
+    fn open_write_append(&self) -> File {
+        self.store.open_write_append()
+    }
 }
 
 #[derive(Clone)]
@@ -199,6 +312,116 @@ impl PersistentLayerStore for DirectoryLayerStore {
     }
 }
 
+/// A file that a layer is expected to have, along with its size if it is present.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerFileReport {
+    pub name: &'static str,
+    pub size: Option<u64>,
+}
+
+/// The files found for a single layer directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerLayout {
+    pub name: [u32; 5],
+    /// whether this layer has a parent file, making it a child layer
+    pub is_child: bool,
+    pub files: Vec<LayerFileReport>,
+}
+
+impl LayerLayout {
+    /// Returns the names of all expected files that are missing from this layer.
+    pub fn missing_files(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.files
+            .iter()
+            .filter(|file| file.size.is_none())
+            .map(|file| file.name)
+    }
+}
+
+/// A report on the layers stored in a `DirectoryLayerStore` directory.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoreLayout {
+    /// all layers found, ordered by name
+    pub layers: Vec<LayerLayout>,
+    /// entries in the store directory that don't follow the layer naming scheme
+    pub unrecognized: Vec<PathBuf>,
+}
+
+fn file_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path)
+        .ok()
+        .filter(|m| m.is_file())
+        .map(|m| m.len())
+}
+
+/// Scan the directory of a `DirectoryLayerStore`, reporting every
+/// layer it contains and which of the layer's files are present.
+///
+/// Missing files are reported as such rather than causing an error,
+/// so that this can be used to diagnose a damaged store. Only failing
+/// to read the directories themselves results in an error.
+pub fn scan_layout<P: AsRef<Path>>(path: P) -> io::Result<StoreLayout> {
+    let mut layers = Vec::new();
+    let mut unrecognized = Vec::new();
+    for prefix_entry in std::fs::read_dir(path)? {
+        let prefix_entry = prefix_entry?;
+        let prefix = prefix_entry.file_name();
+        let prefix = prefix.to_str();
+        if !prefix_entry.file_type()?.is_dir()
+            || prefix.map(|p| p.len() != PREFIX_DIR_SIZE).unwrap_or(true)
+        {
+            unrecognized.push(prefix_entry.path());
+            continue;
+        }
+        let prefix = prefix.unwrap();
+
+        for layer_entry in std::fs::read_dir(prefix_entry.path())? {
+            let layer_entry = layer_entry?;
+            let layer_path = layer_entry.path();
+            let name = layer_entry
+                .file_name()
+                .to_str()
+                .filter(|n| n.starts_with(prefix))
+                .and_then(|n| string_to_name(n).ok());
+            let name = match name {
+                Some(name) if layer_entry.file_type()?.is_dir() => name,
+                _ => {
+                    unrecognized.push(layer_path);
+                    continue;
+                }
+            };
+
+            let is_child = file_size(&layer_path.join(FILENAMES.parent)).is_some();
+            let expected: &[&'static str] = if is_child {
+                &CHILD_LAYER_FILES
+            } else {
+                &BASE_LAYER_FILES
+            };
+            let files = expected
+                .iter()
+                .map(|&file| LayerFileReport {
+                    name: file,
+                    size: file_size(&layer_path.join(file)),
+                })
+                .collect();
+
+            layers.push(LayerLayout {
+                name,
+                is_child,
+                files,
+            });
+        }
+    }
+
+    layers.sort_by_key(|layer| layer.name);
+    unrecognized.sort();
+
+    Ok(StoreLayout {
+        layers,
+        unrecognized,
+    })
+}
+
 #[derive(Clone)]
 pub struct DirectoryLabelStore {
     path: PathBuf,
@@ -301,6 +524,7 @@ impl LabelStore for DirectoryLabelStore {
                 .and_then(|p| {
                     ExclusiveLockedFile::create_and_open(p)
                         .and_then(|f| tokio::io::write_all(f, contents))
+                        .and_then(|(f, _)| f.close())
                         .map(move |_| Label::new_empty(&label))
                 }),
         )
@@ -351,6 +575,7 @@ impl LabelStore for DirectoryLabelStore {
                 let result: Box<dyn Future<Item = _, Error = _> + Send> = Box::new(
                     ExclusiveLockedFile::open(p)
                         .and_then(|f| tokio::io::write_all(f, contents))
+                        .and_then(|(f, _)| f.close())
                         .map(|_| Some(new_label)),
                 );
                 result
@@ -584,4 +809,133 @@ mod tests {
         let error = result.err().unwrap();
         assert_eq!(io::ErrorKind::InvalidInput, error.kind());
     }
+
+    #[test]
+    fn write_and_map_temp_mmap_store() {
+        let file = TempMmapStore::new().unwrap();
+        let path = file.path().to_owned();
+        let runtime = Runtime::new().unwrap();
+
+        let w = file.open_write();
+        let contents: Vec<u8> = (0..MMAP_TRESHOLD + 1).map(|i| (i % 256) as u8).collect();
+
+        let file2 = file.clone();
+        let task = tokio::io::write_all(w, contents.clone()).and_then(move |_| file2.map());
+
+        let map = oneshot::spawn(task, &runtime.executor()).wait().unwrap();
+        runtime.shutdown_now();
+
+        assert_eq!(contents.len(), file.size());
+        assert_eq!(contents, map.as_ref());
+
+        assert!(path.exists());
+        drop(file);
+        assert!(!path.exists());
+
+        // the map outlives the file it was made from
+        assert_eq!(contents, map.as_ref());
+    }
+
+    #[test]
+    fn scan_layout_of_directory_store() {
+        let dir = tempdir().unwrap();
+        let runtime = Runtime::new().unwrap();
+        let store = DirectoryLayerStore::new(dir.path());
+
+        let mut builder = oneshot::spawn(store.create_base_layer(), &runtime.executor())
+            .wait()
+            .unwrap();
+        let base_name = builder.name();
+        builder.add_string_triple(&StringTriple::new_value("cow", "says", "moo"));
+        oneshot::spawn(builder.commit_boxed(), &runtime.executor())
+            .wait()
+            .unwrap();
+
+        let mut builder = oneshot::spawn(store.create_child_layer(base_name), &runtime.executor())
+            .wait()
+            .unwrap();
+        let child_name = builder.name();
+        builder.add_string_triple(&StringTriple::new_value("pig", "says", "oink"));
+        oneshot::spawn(builder.commit_boxed(), &runtime.executor())
+            .wait()
+            .unwrap();
+        runtime.shutdown_now();
+
+        std::fs::write(dir.path().join("stray"), b"").unwrap();
+
+        let layout = scan_layout(dir.path()).unwrap();
+        assert_eq!(2, layout.layers.len());
+        assert_eq!(vec![dir.path().join("stray")], layout.unrecognized);
+
+        let base = layout.layers.iter().find(|l| l.name == base_name).unwrap();
+        let child = layout.layers.iter().find(|l| l.name == child_name).unwrap();
+        assert!(!base.is_child);
+        assert!(child.is_child);
+        assert_eq!(BASE_LAYER_FILES.len(), base.files.len());
+        assert_eq!(CHILD_LAYER_FILES.len(), child.files.len());
+        assert_eq!(0, base.missing_files().count());
+        assert_eq!(0, child.missing_files().count());
+
+        let base_name_string = name_to_string(base_name);
+        std::fs::remove_file(
+            dir.path()
+                .join(&base_name_string[0..PREFIX_DIR_SIZE])
+                .join(&base_name_string)
+                .join(FILENAMES.value_dictionary_offsets),
+        )
+        .unwrap();
+
+        let layout = scan_layout(dir.path()).unwrap();
+        let base = layout.layers.iter().find(|l| l.name == base_name).unwrap();
+        assert_eq!(
+            vec![FILENAMES.value_dictionary_offsets],
+            base.missing_files().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn scan_layout_reports_non_ascii_layer_dirs_as_unrecognized() {
+        let dir = tempdir().unwrap();
+        let prefix_dir = dir.path().join("012");
+        let layer_dir = prefix_dir.join(format!("0123456\u{e9}{}", "0".repeat(31)));
+        std::fs::create_dir_all(&layer_dir).unwrap();
+
+        let layout = scan_layout(dir.path()).unwrap();
+        assert!(layout.layers.is_empty());
+        assert_eq!(vec![layer_dir], layout.unrecognized);
+    }
+
+    #[test]
+    fn write_modes_file_backed() {
+        let dir = tempdir().unwrap();
+        let file = FileBackedStore::new(dir.path().join("foo"));
+        let runtime = Runtime::new().unwrap();
+        let write = |w: File, data: &[u8]| {
+            oneshot::spawn(
+                tokio::io::write_all(w, data.to_vec()).and_then(|(w, _)| tokio::io::shutdown(w)),
+                &runtime.executor(),
+            )
+            .wait()
+            .unwrap()
+        };
+        let contents = || std::fs::read(dir.path().join("foo")).unwrap();
+
+        write(file.open_write(), &[1, 2, 3, 4]);
+        write(file.open_write(), &[9]);
+        assert_eq!(vec![9, 2, 3, 4], contents());
+
+        write(file.open_write_append(), &[5]);
+        assert_eq!(vec![9, 2, 3, 4, 5], contents());
+
+        // an append handle writes at the end as it is when writing, not when opening
+        let append = file.open_write_append();
+        write(file.open_write_from(5), &[6]);
+        write(append, &[8]);
+        assert_eq!(vec![9, 2, 3, 4, 5, 6, 8], contents());
+
+        write(file.open_write_truncate(), &[7]);
+        assert_eq!(vec![7], contents());
+
+        runtime.shutdown_now();
+    }
 }
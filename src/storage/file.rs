@@ -3,12 +3,31 @@
 use futures::prelude::*;
 use tokio::prelude::*;
 
+/// A file that can be written to.
+///
+/// Besides `open_write_from`, implementations have to provide
+/// `open_write_truncate` and `open_write_append`. These have no
+/// default, so implementations written against earlier versions of
+/// this trait need to add them.
 pub trait FileStore: Clone + Send + Sync {
     type Write: AsyncWrite + Send;
+    /// Open the file for writing from the start.
+    ///
+    /// Existing content is overwritten but not truncated, so anything
+    /// beyond what gets written is left in place. Use
+    /// `open_write_truncate` when that is not what you want.
     fn open_write(&self) -> Self::Write {
         self.open_write_from(0)
     }
     fn open_write_from(&self, offset: usize) -> Self::Write;
+    /// Discard all existing content, then open the file for writing from the start.
+    fn open_write_truncate(&self) -> Self::Write;
+    /// Open the file for appending.
+    ///
+    /// Every write goes to the end of the file as it is at the time of
+    /// that write, so content written through other handles after
+    /// opening is never overwritten.
+    fn open_write_append(&self) -> Self::Write;
 }
 
 pub trait FileLoad: Clone + Send + Sync {
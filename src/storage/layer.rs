@@ -1,7 +1,10 @@
-use super::consts::FILENAMES;
+use super::consts::{BASE_LAYER_FILES, CHILD_LAYER_FILES, FILENAMES};
 use super::file::*;
 use crate::layer::{BaseLayer, ChildLayer, Layer, LayerBuilder, LayerType, SimpleLayerBuilder};
+use byteorder::{BigEndian, ByteOrder};
+use std::fmt;
 use std::io;
+use std::str::FromStr;
 use std::sync::{Arc, Weak};
 
 use futures::future;
@@ -92,29 +95,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         &self,
         name: [u32; 5],
     ) -> Box<dyn Future<Item = BaseLayerFiles<Self::File>, Error = io::Error> + Send> {
-        let filenames = vec![
-            FILENAMES.node_dictionary_blocks,
-            FILENAMES.node_dictionary_offsets,
-            FILENAMES.predicate_dictionary_blocks,
-            FILENAMES.predicate_dictionary_offsets,
-            FILENAMES.value_dictionary_blocks,
-            FILENAMES.value_dictionary_offsets,
-            FILENAMES.base_s_p_adjacency_list_bits,
-            FILENAMES.base_s_p_adjacency_list_bit_index_blocks,
-            FILENAMES.base_s_p_adjacency_list_bit_index_sblocks,
-            FILENAMES.base_s_p_adjacency_list_nums,
-            FILENAMES.base_sp_o_adjacency_list_bits,
-            FILENAMES.base_sp_o_adjacency_list_bit_index_blocks,
-            FILENAMES.base_sp_o_adjacency_list_bit_index_sblocks,
-            FILENAMES.base_sp_o_adjacency_list_nums,
-            FILENAMES.base_o_ps_adjacency_list_bits,
-            FILENAMES.base_o_ps_adjacency_list_bit_index_blocks,
-            FILENAMES.base_o_ps_adjacency_list_bit_index_sblocks,
-            FILENAMES.base_o_ps_adjacency_list_nums,
-            FILENAMES.base_predicate_wavelet_tree_bits,
-            FILENAMES.base_predicate_wavelet_tree_bit_index_blocks,
-            FILENAMES.base_predicate_wavelet_tree_bit_index_sblocks,
-        ];
+        let filenames = BASE_LAYER_FILES.to_vec();
 
         let clone = self.clone();
 
@@ -171,48 +152,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
         &self,
         name: [u32; 5],
     ) -> Box<dyn Future<Item = ChildLayerFiles<Self::File>, Error = io::Error> + Send> {
-        let filenames = vec![
-            FILENAMES.node_dictionary_blocks,
-            FILENAMES.node_dictionary_offsets,
-            FILENAMES.predicate_dictionary_blocks,
-            FILENAMES.predicate_dictionary_offsets,
-            FILENAMES.value_dictionary_blocks,
-            FILENAMES.value_dictionary_offsets,
-            FILENAMES.pos_subjects,
-            FILENAMES.pos_objects,
-            FILENAMES.neg_subjects,
-            FILENAMES.neg_objects,
-            FILENAMES.pos_s_p_adjacency_list_bits,
-            FILENAMES.pos_s_p_adjacency_list_bit_index_blocks,
-            FILENAMES.pos_s_p_adjacency_list_bit_index_sblocks,
-            FILENAMES.pos_s_p_adjacency_list_nums,
-            FILENAMES.pos_sp_o_adjacency_list_bits,
-            FILENAMES.pos_sp_o_adjacency_list_bit_index_blocks,
-            FILENAMES.pos_sp_o_adjacency_list_bit_index_sblocks,
-            FILENAMES.pos_sp_o_adjacency_list_nums,
-            FILENAMES.pos_o_ps_adjacency_list_bits,
-            FILENAMES.pos_o_ps_adjacency_list_bit_index_blocks,
-            FILENAMES.pos_o_ps_adjacency_list_bit_index_sblocks,
-            FILENAMES.pos_o_ps_adjacency_list_nums,
-            FILENAMES.neg_s_p_adjacency_list_bits,
-            FILENAMES.neg_s_p_adjacency_list_bit_index_blocks,
-            FILENAMES.neg_s_p_adjacency_list_bit_index_sblocks,
-            FILENAMES.neg_s_p_adjacency_list_nums,
-            FILENAMES.neg_sp_o_adjacency_list_bits,
-            FILENAMES.neg_sp_o_adjacency_list_bit_index_blocks,
-            FILENAMES.neg_sp_o_adjacency_list_bit_index_sblocks,
-            FILENAMES.neg_sp_o_adjacency_list_nums,
-            FILENAMES.neg_o_ps_adjacency_list_bits,
-            FILENAMES.neg_o_ps_adjacency_list_bit_index_blocks,
-            FILENAMES.neg_o_ps_adjacency_list_bit_index_sblocks,
-            FILENAMES.neg_o_ps_adjacency_list_nums,
-            FILENAMES.pos_predicate_wavelet_tree_bits,
-            FILENAMES.pos_predicate_wavelet_tree_bit_index_blocks,
-            FILENAMES.pos_predicate_wavelet_tree_bit_index_sblocks,
-            FILENAMES.neg_predicate_wavelet_tree_bits,
-            FILENAMES.neg_predicate_wavelet_tree_bit_index_blocks,
-            FILENAMES.neg_predicate_wavelet_tree_bit_index_sblocks,
-        ];
+        let filenames = CHILD_LAYER_FILES.to_vec();
 
         let cloned = self.clone();
 
@@ -309,7 +249,7 @@ pub trait PersistentLayerStore: 'static + Send + Sync + Clone {
 
         Box::new(
             self.get_file(dir_name, FILENAMES.parent)
-                .map(|f| f.open_write())
+                .map(|f| f.open_write_truncate())
                 .and_then(|writer| tokio::io::write_all(writer, parent_string))
                 .map(|_| ()),
         )
@@ -369,6 +309,12 @@ pub fn string_to_name(string: &str) -> Result<[u32; 5], std::io::Error> {
     if string.len() != 40 {
         return Err(io::Error::new(io::ErrorKind::Other, "string not len 40"));
     }
+    if !string.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "string not hexadecimal",
+        ));
+    }
     let n1 = u32::from_str_radix(&string[..8], 16)
         .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
     let n2 = u32::from_str_radix(&string[8..16], 16)
@@ -383,6 +329,65 @@ pub fn string_to_name(string: &str) -> Result<[u32; 5], std::io::Error> {
     Ok([n1, n2, n3, n4, n5])
 }
 
+/// A layer id, with a canonical textual form of 40 lowercase hex digits.
+///
+/// Parsing accepts hex digits of either case, but ids are always
+/// displayed in lowercase.
+///
+/// Layer ids are passed around as bare `[u32; 5]` arrays throughout
+/// the store. This wraps one to give it a `Display` and `FromStr`
+/// implementation, and a big-endian byte packing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LayerId(pub [u32; 5]);
+
+impl LayerId {
+    /// Returns the id as 20 bytes, with each component in big-endian order.
+    pub fn to_bytes(&self) -> [u8; 20] {
+        let mut bytes = [0; 20];
+        for (chunk, &n) in bytes.chunks_mut(4).zip(self.0.iter()) {
+            BigEndian::write_u32(chunk, n);
+        }
+
+        bytes
+    }
+
+    /// Reads an id from 20 bytes, with each component in big-endian order.
+    pub fn from_bytes(bytes: &[u8; 20]) -> LayerId {
+        let mut name = [0; 5];
+        for (n, chunk) in name.iter_mut().zip(bytes.chunks(4)) {
+            *n = BigEndian::read_u32(chunk);
+        }
+
+        LayerId(name)
+    }
+}
+
+impl From<[u32; 5]> for LayerId {
+    fn from(name: [u32; 5]) -> LayerId {
+        LayerId(name)
+    }
+}
+
+impl From<LayerId> for [u32; 5] {
+    fn from(id: LayerId) -> [u32; 5] {
+        id.0
+    }
+}
+
+impl fmt::Display for LayerId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", name_to_string(self.0))
+    }
+}
+
+impl FromStr for LayerId {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<LayerId, io::Error> {
+        string_to_name(s).map(LayerId)
+    }
+}
+
 pub fn bytes_to_name(bytes: &Vec<u8>) -> Result<[u32; 5], std::io::Error> {
     if bytes.len() != 40 {
         Err(io::Error::new(io::ErrorKind::Other, "bytes not len 40"))
@@ -770,4 +775,37 @@ pub mod tests {
         //let store = CachedLayerStore::new(MemoryLayerStore::new());
         //let builder = store.create_base_layer().wait().unwrap();
     }
+
+    #[test]
+    fn layer_id_round_trips_through_string() {
+        let id = LayerId([0x0123_4567, 0x89ab_cdef, 0, 1, 0xffff_ffff]);
+        let s = id.to_string();
+        assert_eq!("0123456789abcdef0000000000000001ffffffff", s);
+        assert_eq!(id, s.parse().unwrap());
+        assert_eq!(name_to_string(id.0), s);
+        assert_eq!(id, s.to_uppercase().parse().unwrap());
+    }
+
+    #[test]
+    fn layer_id_rejects_malformed_strings() {
+        assert!("0123".parse::<LayerId>().is_err());
+        assert!("0123456789abcdef0000000000000001fffffffg"
+            .parse::<LayerId>()
+            .is_err());
+        assert!("+123456789abcdef0000000000000001ffffffff"
+            .parse::<LayerId>()
+            .is_err());
+        assert!(format!("0123456\u{e9}{}", "0".repeat(31))
+            .parse::<LayerId>()
+            .is_err());
+    }
+
+    #[test]
+    fn layer_id_round_trips_through_bytes() {
+        let id = LayerId([0x0123_4567, 0x89ab_cdef, 0, 1, 0xffff_ffff]);
+        let bytes = id.to_bytes();
+        assert_eq!([0x01, 0x23, 0x45, 0x67, 0x89], bytes[..5]);
+        assert_eq!([0, 0, 0, 1, 0xff, 0xff, 0xff, 0xff], bytes[12..]);
+        assert_eq!(id, LayerId::from_bytes(&bytes));
+    }
 }
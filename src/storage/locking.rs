@@ -1,12 +1,48 @@
 #![allow(unused)]
 use crate::storage::{layer, Label};
 use fs2::*;
+use std::error::Error;
+use std::fmt::{self, Display};
 use std::io::{self, SeekFrom};
 use std::path::*;
+use std::time::{Duration, Instant};
 use tokio::fs;
 use tokio::prelude::*;
+use tokio::timer::Delay;
 use tokio_threadpool::blocking;
 
+const LOCK_RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(1);
+const LOCK_RETRY_MAX_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The error returned when a lock could not be acquired in time because
+/// another handle kept holding it.
+///
+/// This is wrapped in an `io::Error` of kind `WouldBlock`.
+#[derive(Debug)]
+pub struct LockTimeout {
+    pub path: PathBuf,
+    pub waited: Duration,
+}
+
+impl Display for LockTimeout {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "lock on {} still contended after {:?}",
+            self.path.display(),
+            self.waited
+        )
+    }
+}
+
+impl Error for LockTimeout {}
+
+impl From<LockTimeout> for io::Error {
+    fn from(err: LockTimeout) -> io::Error {
+        io::Error::new(io::ErrorKind::WouldBlock, err)
+    }
+}
+
 pub struct LockedFileLockFuture {
     file: Option<std::fs::File>,
     exclusive: bool,
@@ -113,6 +149,65 @@ impl LockedFile {
             ),
         })
     }
+
+    /// Like `create_and_open`, but rather than waiting for a contended
+    /// lock indefinitely, retry with an increasing backoff until the
+    /// timeout passes.
+    ///
+    /// If the lock is still held by someone else at that point, this
+    /// fails with an `io::Error` of kind `WouldBlock` wrapping a `LockTimeout`.
+    pub fn create_and_open_with_timeout<P: 'static + AsRef<Path> + Send>(
+        path: P,
+        timeout: Duration,
+    ) -> impl Future<Item = Self, Error = io::Error> + Send {
+        let path = PathBuf::from(path.as_ref());
+        let start = Instant::now();
+        let deadline = start + timeout;
+        fs::OpenOptions::new()
+            .write(true)
+            .truncate(false)
+            .create(true)
+            .open(path.clone())
+            .and_then(tokio::io::shutdown)
+            .and_then({
+                let path = path.clone();
+                move |_| fs::OpenOptions::new().read(true).open(path)
+            })
+            .map(|f| f.into_std())
+            .and_then(move |f| {
+                future::loop_fn((f, LOCK_RETRY_INITIAL_BACKOFF), move |(f, backoff)| {
+                    match FileExt::try_lock_shared(&f) {
+                        Ok(()) => future::Either::A(future::ok(future::Loop::Break(f))),
+                        Err(e) if e.kind() != lock_contended_error().kind() => {
+                            future::Either::A(future::err(e))
+                        }
+                        Err(_) => {
+                            let now = Instant::now();
+                            if now >= deadline {
+                                return future::Either::A(future::err(
+                                    LockTimeout {
+                                        path: path.clone(),
+                                        waited: now - start,
+                                    }
+                                    .into(),
+                                ));
+                            }
+
+                            let wake = std::cmp::min(now + backoff, deadline);
+                            let next_backoff = std::cmp::min(backoff * 2, LOCK_RETRY_MAX_BACKOFF);
+                            future::Either::B(
+                                Delay::new(wake)
+                                    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+                                    .map(move |_| future::Loop::Continue((f, next_backoff))),
+                            )
+                        }
+                    }
+                })
+            })
+            .map(|f| LockedFile {
+                file: Some(fs::File::from_std(f)),
+            })
+    }
 }
 
 impl Read for LockedFile {
@@ -188,6 +283,32 @@ impl ExclusiveLockedFile {
     pub fn do_shutdown(mut self) -> impl Future<Item = (), Error = io::Error> + Send {
         future::poll_fn(move || self.shutdown())
     }
+
+    /// Flush and fsync this file, then release its lock.
+    ///
+    /// Unlike `do_shutdown`, which only flushes pending writes before
+    /// unlocking, this waits for the file contents to reach the
+    /// storage device. Once the returned future resolves, it is safe to
+    /// hand the file over to another process.
+    pub fn close(mut self) -> impl Future<Item = (), Error = io::Error> + Send {
+        future::poll_fn(move || {
+            let file = self
+                .file
+                .as_mut()
+                .expect("tried to close dropped ExclusiveLockedFile");
+            if let Async::NotReady = file.poll_flush()? {
+                return Ok(Async::NotReady);
+            }
+            if let Async::NotReady = file.poll_sync_all()? {
+                return Ok(Async::NotReady);
+            }
+
+            let file = self.file.take().unwrap();
+            FileExt::unlock(&file.into_std())?;
+
+            Ok(Async::Ready(()))
+        })
+    }
 }
 
 struct SetLenFuture {
@@ -320,3 +441,102 @@ impl FutureSeekable for ExclusiveLockedFile {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::sync::oneshot;
+    use tempfile::tempdir;
+    use tokio::runtime::Runtime;
+
+    #[test]
+    fn create_and_open_with_timeout_gives_up_on_contended_lock() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("foo");
+        let holder = std::fs::File::create(&path).unwrap();
+        FileExt::lock_exclusive(&holder).unwrap();
+
+        let runtime = Runtime::new().unwrap();
+        let result = oneshot::spawn(
+            LockedFile::create_and_open_with_timeout(path.clone(), Duration::from_millis(50)),
+            &runtime.executor(),
+        )
+        .wait();
+
+        let err = result.unwrap_err();
+        assert_eq!(io::ErrorKind::WouldBlock, err.kind());
+        let timeout = err
+            .get_ref()
+            .unwrap()
+            .downcast_ref::<LockTimeout>()
+            .unwrap();
+        assert_eq!(path, timeout.path);
+        assert!(timeout.waited >= Duration::from_millis(50));
+
+        FileExt::unlock(&holder).unwrap();
+        let file = oneshot::spawn(
+            LockedFile::create_and_open_with_timeout(path, Duration::from_millis(50)),
+            &runtime.executor(),
+        )
+        .wait();
+        assert!(file.is_ok());
+
+        drop(file);
+        runtime.shutdown_now();
+    }
+
+    #[test]
+    fn create_and_open_with_timeout_waits_for_release() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("foo");
+        let holder = std::fs::File::create(&path).unwrap();
+        FileExt::lock_exclusive(&holder).unwrap();
+
+        let releaser = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(20));
+            FileExt::unlock(&holder).unwrap();
+        });
+
+        let runtime = Runtime::new().unwrap();
+        let file = oneshot::spawn(
+            LockedFile::create_and_open_with_timeout(path, Duration::from_secs(10)),
+            &runtime.executor(),
+        )
+        .wait();
+        releaser.join().unwrap();
+
+        assert!(file.is_ok());
+        drop(file);
+        runtime.shutdown_now();
+    }
+
+    #[test]
+    fn close_exclusive_file_releases_lock() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("foo");
+
+        let runtime = Runtime::new().unwrap();
+        let path1 = path.clone();
+        let path2 = path.clone();
+        let task = ExclusiveLockedFile::create_and_open(path)
+            .and_then(|file| tokio::io::write_all(file, [1, 2, 3]))
+            .and_then(move |(file, _)| {
+                let other = std::fs::File::open(&path1).unwrap();
+                assert!(FileExt::try_lock_exclusive(&other).is_err());
+
+                file.close()
+            })
+            .map(move |_| {
+                // the closed file is still owned by the close future at
+                // this point, so it is close that released the lock, not drop.
+                let other = std::fs::File::open(&path2).unwrap();
+                FileExt::try_lock_exclusive(&other).unwrap();
+
+                std::fs::read(&path2).unwrap()
+            });
+        let contents = oneshot::spawn(task, &runtime.executor()).wait().unwrap();
+        runtime.shutdown_now();
+
+        assert_eq!(vec![1, 2, 3], contents);
+    }
+}
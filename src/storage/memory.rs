@@ -12,11 +12,15 @@ use crate::layer::{BaseLayer, ChildLayer, Layer, LayerBuilder, SimpleLayerBuilde
 pub struct MemoryBackedStoreWriter {
     vec: Arc<sync::RwLock<Vec<u8>>>,
     pos: usize,
+    append: bool,
 }
 
 impl Write for MemoryBackedStoreWriter {
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
         let mut v = self.vec.write().unwrap();
+        if self.append {
+            self.pos = v.len();
+        }
         if v.len() - self.pos < buf.len() {
             v.resize(self.pos + buf.len(), 0);
         }
@@ -98,6 +102,21 @@ impl FileStore for MemoryBackedStore {
         MemoryBackedStoreWriter {
             vec: self.vec.clone(),
             pos,
+            append: false,
+        }
+    }
+
+    fn open_write_truncate(&self) -> MemoryBackedStoreWriter {
+        self.vec.write().unwrap().clear();
+
+        self.open_write_from(0)
+    }
+
+    fn open_write_append(&self) -> MemoryBackedStoreWriter {
+        MemoryBackedStoreWriter {
+            vec: self.vec.clone(),
+            pos: 0,
+            append: true,
         }
     }
 }
@@ -125,6 +144,72 @@ impl FileLoad for MemoryBackedStore {
     }
 }
 
+/// A read-only file backed by bytes that are already in memory.
+///
+/// Mapping this file hands out the shared bytes as they are, without
+/// copying them. As it is read-only, it does not implement `FileStore`.
+#[derive(Clone, Debug)]
+pub struct SliceStore {
+    data: Arc<[u8]>,
+}
+
+impl SliceStore {
+    pub fn new<D: Into<Arc<[u8]>>>(data: D) -> SliceStore {
+        SliceStore { data: data.into() }
+    }
+}
+
+pub struct SliceStoreReader {
+    data: Arc<[u8]>,
+    pos: usize,
+}
+
+impl Read for SliceStoreReader {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        if self.pos >= self.data.len() {
+            return Ok(0);
+        }
+
+        let slice = &self.data[self.pos..];
+        let len = std::cmp::min(slice.len(), buf.len());
+        buf[..len].copy_from_slice(&slice[..len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+impl AsyncRead for SliceStoreReader {}
+
+#[derive(Clone, Debug)]
+pub struct SharedSlice(pub Arc<[u8]>);
+
+impl AsRef<[u8]> for SharedSlice {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl FileLoad for SliceStore {
+    type Read = SliceStoreReader;
+    type Map = SharedSlice;
+
+    fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn open_read_from(&self, offset: usize) -> SliceStoreReader {
+        SliceStoreReader {
+            data: self.data.clone(),
+            pos: offset,
+        }
+    }
+
+    fn map(&self) -> Box<dyn Future<Item = SharedSlice, Error = std::io::Error> + Send> {
+        Box::new(future::ok(SharedSlice(self.data.clone())))
+    }
+}
+
 #[derive(Clone)]
 pub struct MemoryLayerStore {
     layers:
@@ -608,4 +693,70 @@ mod tests {
             .unwrap()
             .is_some());
     }
+
+    #[test]
+    fn read_and_map_slice_store() {
+        let file = SliceStore::new(vec![1, 2, 3, 4, 5]);
+        assert_eq!(5, file.size());
+
+        let buf = tokio::io::read_to_end(file.open_read_from(2), Vec::new())
+            .map(|(_, buf)| buf)
+            .wait()
+            .unwrap();
+        assert_eq!(vec![3, 4, 5], buf);
+
+        let map = file.map().wait().unwrap();
+        assert_eq!(&[1, 2, 3, 4, 5], map.as_ref());
+        // mapping does not copy the underlying bytes
+        assert!(std::ptr::eq(file.data.as_ptr(), map.as_ref().as_ptr()));
+    }
+
+    #[test]
+    fn parse_dictionary_from_slice_store() {
+        use crate::structure::{PfcDict, PfcDictFileBuilder};
+
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write())
+            .add_all(vec!["aaa".to_string(), "bbb".to_string()].into_iter())
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        let blocks = SliceStore::new(blocks.map().wait().unwrap().0.to_vec());
+        let offsets = SliceStore::new(offsets.map().wait().unwrap().0.to_vec());
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+
+        assert_eq!(Some(1), dict.id("bbb"));
+        assert_eq!(Some("aaa".to_string()), dict.get(0));
+    }
+
+    #[test]
+    fn write_modes_memory_backed() {
+        let file = MemoryBackedStore::new();
+        let write = |w, data: &[u8]| {
+            tokio::io::write_all(w, data.to_vec())
+                .and_then(|(w, _)| tokio::io::shutdown(w))
+                .wait()
+                .unwrap()
+        };
+        let contents = |file: &MemoryBackedStore| file.map().wait().unwrap().0.to_vec();
+
+        write(file.open_write(), &[1, 2, 3, 4]);
+        write(file.open_write(), &[9]);
+        assert_eq!(vec![9, 2, 3, 4], contents(&file));
+
+        write(file.open_write_append(), &[5]);
+        assert_eq!(vec![9, 2, 3, 4, 5], contents(&file));
+
+        // an append handle writes at the end as it is when writing, not when opening
+        let append = file.open_write_append();
+        write(file.open_write_from(5), &[6]);
+        write(append, &[8]);
+        assert_eq!(vec![9, 2, 3, 4, 5, 6, 8], contents(&file));
+
+        write(file.open_write_truncate(), &[7]);
+        assert_eq!(vec![7], contents(&file));
+    }
 }
@@ -33,3 +33,4 @@ pub mod memory;
 pub use file::*;
 pub use label::*;
 pub use layer::*;
+pub use locking::{LockTimeout, LockedFile};
@@ -220,7 +220,7 @@ where
         nums_writer: W3,
         width: u8,
     ) -> AdjacencyListBuilder<F, W1, W2, W3> {
-        let bitarray = BitArrayFileBuilder::new(bitfile.open_write());
+        let bitarray = BitArrayFileBuilder::new(bitfile.open_write_truncate());
 
         let nums = LogArrayFileBuilder::new(nums_writer, width);
 
@@ -33,6 +33,11 @@ impl<M: AsRef<[u8]>> BitArray<M> {
         self.count as usize // TODO on 32 bit platform this'll cut off
     }
 
+    /// Returns the size in bytes of the underlying buffer, including the trailing bit count.
+    pub fn heap_size(&self) -> usize {
+        self.bits.as_ref().len()
+    }
+
     pub fn get(&self, index: usize) -> bool {
         if index > self.len() {
             panic!("index too high");
@@ -4,6 +4,7 @@ use byteorder::{BigEndian, ByteOrder};
 use super::bitarray::*;
 use super::logarray::*;
 use futures::prelude::*;
+use std::{error, fmt, io};
 use tokio::prelude::*;
 
 // a block is 64 bit, which is the register size on modern architectures
@@ -12,6 +13,60 @@ use tokio::prelude::*;
 /// The amount of 64-bit blocks that go into a superblock.
 const SBLOCK_SIZE: usize = 52;
 
+/// An error that occurred while constructing a bit index from its parts.
+#[derive(Debug, PartialEq)]
+pub enum BitIndexError {
+    /// the bitarray buffer has the given length, which is not a
+    /// nonzero multiple of 8
+    InvalidBitArray(usize),
+    /// the bitarray claims to hold the first amount of bits, but its buffer only has room for the second
+    BitCountTooLarge(u64, u64),
+    LogArray(LogArrayError),
+    /// the blocks logarray has the first length, but the bitarray requires the second
+    BlocksLengthMismatch(usize, usize),
+    /// the sblocks logarray has the first length, but the blocks require the second
+    SblocksLengthMismatch(usize, usize),
+}
+
+impl fmt::Display for BitIndexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use BitIndexError::*;
+        match self {
+            InvalidBitArray(len) => write!(
+                f,
+                "expected bitarray buffer size ({}) to be a nonzero multiple of 8",
+                len
+            ),
+            BitCountTooLarge(count, capacity) => write!(
+                f,
+                "expected bit count ({}) to fit in the bitarray buffer ({} bits)",
+                count, capacity
+            ),
+            LogArray(err) => write!(f, "{}", err),
+            BlocksLengthMismatch(actual, expected) => {
+                write!(f, "expected blocks length ({}) to be {}", actual, expected)
+            }
+            SblocksLengthMismatch(actual, expected) => {
+                write!(f, "expected sblocks length ({}) to be {}", actual, expected)
+            }
+        }
+    }
+}
+
+impl error::Error for BitIndexError {}
+
+impl From<LogArrayError> for BitIndexError {
+    fn from(err: LogArrayError) -> BitIndexError {
+        BitIndexError::LogArray(err)
+    }
+}
+
+impl From<BitIndexError> for io::Error {
+    fn from(err: BitIndexError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 /// A bitarray with an index, supporting rank and select queries.
 #[derive(Clone)]
 pub struct BitIndex<M: AsRef<[u8]>> {
@@ -29,19 +84,69 @@ impl<M: AsRef<[u8]>> BitIndex<M> {
         BitIndex::from_parts(bitarray, blocks_logarray, sblocks_logarray)
     }
 
+    /// Like `from_maps`, but returns an error instead of panicking
+    /// when the maps are malformed or inconsistent with each other.
+    pub fn try_from_maps(
+        bitarray_map: M,
+        blocks_map: M,
+        sblocks_map: M,
+    ) -> Result<BitIndex<M>, BitIndexError> {
+        let bitarray_len = bitarray_map.as_ref().len();
+        if bitarray_len < 8 || bitarray_len % 8 != 0 {
+            return Err(BitIndexError::InvalidBitArray(bitarray_len));
+        }
+
+        let bitarray = BitArray::from_bits(bitarray_map);
+        let blocks_logarray = LogArray::parse(blocks_map)?;
+        let sblocks_logarray = LogArray::parse(sblocks_map)?;
+
+        BitIndex::try_from_parts(bitarray, blocks_logarray, sblocks_logarray)
+    }
+
     pub fn from_parts(
         array: BitArray<M>,
         blocks: LogArray<M>,
         sblocks: LogArray<M>,
     ) -> BitIndex<M> {
-        assert!(sblocks.len() == (blocks.len() + SBLOCK_SIZE - 1) / SBLOCK_SIZE);
-        assert!(blocks.len() == (array.len() + 63) / 64);
+        BitIndex::try_from_parts(array, blocks, sblocks).unwrap()
+    }
 
-        BitIndex {
+    /// Like `from_parts`, but returns an error instead of panicking
+    /// when the blocks or sblocks don't match the bitarray.
+    pub fn try_from_parts(
+        array: BitArray<M>,
+        blocks: LogArray<M>,
+        sblocks: LogArray<M>,
+    ) -> Result<BitIndex<M>, BitIndexError> {
+        let capacity = array.bits().len() as u64 * 8;
+        if array.len() as u64 > capacity {
+            return Err(BitIndexError::BitCountTooLarge(
+                array.len() as u64,
+                capacity,
+            ));
+        }
+
+        let expected_blocks = (array.len() + 63) / 64;
+        if blocks.len() != expected_blocks {
+            return Err(BitIndexError::BlocksLengthMismatch(
+                blocks.len(),
+                expected_blocks,
+            ));
+        }
+
+        let expected_sblocks = (blocks.len() + SBLOCK_SIZE - 1) / SBLOCK_SIZE;
+        if sblocks.len() != expected_sblocks {
+            return Err(BitIndexError::SblocksLengthMismatch(
+                sblocks.len(),
+                expected_sblocks,
+            ));
+        }
+
+        Ok(BitIndex {
             array,
             blocks,
             sblocks,
-        }
+        })
     }
 
     fn block_bits(&self, block_index: usize) -> &[u8] {
@@ -55,6 +160,11 @@ impl<M: AsRef<[u8]>> BitIndex<M> {
         self.array.len()
     }
 
+    /// Returns the size in bytes of the bitarray, blocks and sblocks buffers together.
+    pub fn heap_size(&self) -> usize {
+        self.array.heap_size() + self.blocks.heap_size() + self.sblocks.heap_size()
+    }
+
     /// Returns the bit at the given index.
     pub fn get(&self, index: u64) -> bool {
         self.array.get(index as usize)
@@ -368,6 +478,7 @@ mod tests {
     use super::*;
     use crate::storage::memory::*;
     use crate::storage::*;
+    use std::sync::Arc;
 
     #[test]
     pub fn rank1_works() {
@@ -640,4 +751,127 @@ mod tests {
         assert_eq!(Some(10), index.select0_from_range(4, 5, 11));
         assert_eq!(None, index.select0_from_range(123456, 5, 10));
     }
+
+    fn build_index_files(len: usize) -> (MemoryBackedStore, MemoryBackedStore, MemoryBackedStore) {
+        let bits = MemoryBackedStore::new();
+        let ba_builder = BitArrayFileBuilder::new(bits.open_write());
+        let contents = (0..).map(|n| n % 3 == 0).take(len);
+        ba_builder
+            .push_all(stream::iter_ok(contents))
+            .and_then(|b| b.finalize())
+            .wait()
+            .unwrap();
+
+        let index_blocks = MemoryBackedStore::new();
+        let index_sblocks = MemoryBackedStore::new();
+        build_bitindex(
+            bits.open_read(),
+            index_blocks.open_write(),
+            index_sblocks.open_write(),
+        )
+        .wait()
+        .unwrap();
+
+        (bits, index_blocks, index_sblocks)
+    }
+
+    #[test]
+    pub fn try_from_maps_accepts_consistent_maps() {
+        let (bits, index_blocks, index_sblocks) = build_index_files(123456);
+        let index = BitIndex::try_from_maps(
+            bits.map().wait().unwrap(),
+            index_blocks.map().wait().unwrap(),
+            index_sblocks.map().wait().unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(123456, index.len());
+        assert_eq!(41152, index.rank1(123455));
+    }
+
+    #[test]
+    pub fn try_from_maps_rejects_truncated_sblocks() {
+        let (bits, index_blocks, index_sblocks) = build_index_files(123456);
+
+        // cutting bytes off the sblocks buffer breaks its control word
+        let sblocks = index_sblocks.map().wait().unwrap().0;
+        let truncated = SharedVec(Arc::new(sblocks[..sblocks.len() - 3].to_vec()));
+        let result = BitIndex::try_from_maps(
+            bits.map().wait().unwrap(),
+            index_blocks.map().wait().unwrap(),
+            truncated,
+        );
+        match result {
+            Err(BitIndexError::LogArray(_)) => {}
+            _ => panic!("expected a logarray error"),
+        }
+
+        // a well-formed sblocks logarray that belongs to a shorter bitarray
+        let (_, _, short_sblocks) = build_index_files(1234);
+        let result = BitIndex::try_from_maps(
+            bits.map().wait().unwrap(),
+            index_blocks.map().wait().unwrap(),
+            short_sblocks.map().wait().unwrap(),
+        );
+        match result {
+            Err(BitIndexError::SblocksLengthMismatch(1, 38)) => {}
+            _ => panic!("expected an sblocks length mismatch"),
+        }
+    }
+
+    #[test]
+    pub fn try_from_maps_rejects_mismatched_blocks() {
+        let (bits, _, _) = build_index_files(123456);
+        let (_, short_blocks, short_sblocks) = build_index_files(1234);
+        let result = BitIndex::try_from_maps(
+            bits.map().wait().unwrap(),
+            short_blocks.map().wait().unwrap(),
+            short_sblocks.map().wait().unwrap(),
+        );
+        match result {
+            Err(BitIndexError::BlocksLengthMismatch(20, 1929)) => {}
+            _ => panic!("expected a blocks length mismatch"),
+        }
+
+        let result = BitIndex::try_from_maps(
+            SharedVec(Arc::new(vec![0; 5])),
+            short_blocks.map().wait().unwrap(),
+            short_sblocks.map().wait().unwrap(),
+        );
+        match result {
+            Err(BitIndexError::InvalidBitArray(5)) => {}
+            _ => panic!("expected an invalid bitarray error"),
+        }
+    }
+
+    #[test]
+    pub fn try_from_maps_rejects_bit_count_beyond_buffer() {
+        // 8 bytes of bits, followed by a count claiming 128 bits
+        let mut bits = vec![0xff; 8];
+        bits.extend_from_slice(&128_u64.to_be_bytes());
+
+        let blocks = MemoryBackedStore::new();
+        LogArrayFileBuilder::new(blocks.open_write(), 64)
+            .push_all(stream::iter_ok(vec![64, 64]))
+            .and_then(|b| b.finalize())
+            .wait()
+            .unwrap();
+
+        let sblocks = MemoryBackedStore::new();
+        LogArrayFileBuilder::new(sblocks.open_write(), 64)
+            .push_all(stream::iter_ok(vec![128]))
+            .and_then(|b| b.finalize())
+            .wait()
+            .unwrap();
+
+        let result = BitIndex::try_from_maps(
+            SharedVec(Arc::new(bits)),
+            blocks.map().wait().unwrap(),
+            sblocks.map().wait().unwrap(),
+        );
+        match result {
+            Err(BitIndexError::BitCountTooLarge(128, 64)) => {}
+            _ => panic!("expected a bit count error"),
+        }
+    }
 }
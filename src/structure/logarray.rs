@@ -212,6 +212,11 @@ impl<M: AsRef<[u8]>> LogArray<M> {
         self.width
     }
 
+    /// Returns the size in bytes of the data buffer, including the control word.
+    pub fn heap_size(&self) -> usize {
+        self.data.as_ref().len()
+    }
+
     /// Reads the data buffer and returns the element at the given index.
     ///
     /// Panics if `index` is >= the length of the log array.
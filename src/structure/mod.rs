@@ -7,7 +7,7 @@ pub mod bitarray;
 pub mod bitindex;
 pub mod logarray;
 pub mod pfc;
-mod util;
+pub(crate) mod util;
 pub mod vbyte;
 pub mod wavelettree;
 
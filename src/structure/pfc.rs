@@ -2,6 +2,7 @@
 use byteorder::{BigEndian, ByteOrder};
 use futures::future;
 use futures::prelude::*;
+use futures::stream;
 use std::cmp::{Ord, Ordering};
 use std::error::Error;
 use std::fmt::Display;
@@ -9,11 +10,17 @@ use std::fmt::Display;
 use super::logarray::*;
 use super::util::*;
 use super::vbyte;
+use crate::storage::*;
+use std::iter::{self, Peekable};
 
 #[derive(Debug)]
 pub enum PfcError {
     InvalidCoding,
     NotEnoughData,
+    /// a string was added to a dictionary builder that was not greater than the string before it
+    OutOfOrder,
+    /// a string containing a nul byte was added to a dictionary builder
+    NulByte,
 }
 
 impl Display for PfcError {
@@ -32,7 +39,11 @@ impl Error for PfcError {}
 
 impl Into<std::io::Error> for PfcError {
     fn into(self) -> std::io::Error {
-        std::io::Error::new(std::io::ErrorKind::InvalidData, self)
+        let kind = match self {
+            PfcError::OutOfOrder | PfcError::NulByte => std::io::ErrorKind::InvalidInput,
+            _ => std::io::ErrorKind::InvalidData,
+        };
+        std::io::Error::new(kind, self)
     }
 }
 
@@ -43,6 +54,31 @@ pub struct PfcBlock<M: AsRef<[u8]>> {
 }
 
 const BLOCK_SIZE: usize = 8;
+const MAX_BLOCK_SIZE: usize = 255;
+
+// The blocks file ends in a big-endian u64 holding the amount of
+// strings. Its most significant byte holds the block size, with 0
+// standing for the default block size, so that files written before
+// the block size was configurable are still read correctly.
+fn encode_trailer(n_strings: u64, block_size: usize) -> u64 {
+    let block_size_code = if block_size == BLOCK_SIZE {
+        0
+    } else {
+        block_size as u64
+    };
+
+    n_strings | (block_size_code << 56)
+}
+
+fn decode_trailer(trailer: u64) -> (u64, usize) {
+    let n_strings = trailer & ((1 << 56) - 1);
+    let block_size = match (trailer >> 56) as usize {
+        0 => BLOCK_SIZE,
+        block_size => block_size,
+    };
+
+    (n_strings, block_size)
+}
 
 pub struct PfcBlockIterator<'a, M: AsRef<[u8]>> {
     block: &'a PfcBlock<M>,
@@ -51,10 +87,9 @@ pub struct PfcBlockIterator<'a, M: AsRef<[u8]>> {
     string: Vec<u8>,
 }
 
-impl<'a, M: AsRef<[u8]>> Iterator for PfcBlockIterator<'a, M> {
-    type Item = String;
-
-    fn next(&mut self) -> Option<String> {
+impl<'a, M: AsRef<[u8]>> PfcBlockIterator<'a, M> {
+    /// Returns the next string in this block as raw bytes, without checking that it is valid UTF-8.
+    pub fn next_bytes(&mut self) -> Option<Vec<u8>> {
         if self.pos == 0 {
             // we gotta read the initial prefix first (a nul-terminated string)
             self.string = self.block.head();
@@ -85,7 +120,16 @@ impl<'a, M: AsRef<[u8]>> Iterator for PfcBlockIterator<'a, M> {
             return None;
         }
 
-        Some(String::from_utf8(self.string.clone()).unwrap())
+        Some(self.string.clone())
+    }
+}
+
+impl<'a, M: AsRef<[u8]>> Iterator for PfcBlockIterator<'a, M> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.next_bytes()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
     }
 }
 
@@ -97,10 +141,9 @@ pub struct OwnedPfcBlockIterator<M: AsRef<[u8]>> {
     string: Vec<u8>,
 }
 
-impl<M: AsRef<[u8]>> Iterator for OwnedPfcBlockIterator<M> {
-    type Item = String;
-
-    fn next(&mut self) -> Option<String> {
+impl<M: AsRef<[u8]>> OwnedPfcBlockIterator<M> {
+    /// Returns the next string in this block as raw bytes, without checking that it is valid UTF-8.
+    pub fn next_bytes(&mut self) -> Option<Vec<u8>> {
         if self.pos == 0 {
             // we gotta read the initial prefix first (a nul-terminated string)
             self.string = self.block.head();
@@ -131,7 +174,16 @@ impl<M: AsRef<[u8]>> Iterator for OwnedPfcBlockIterator<M> {
             return None;
         }
 
-        Some(String::from_utf8(self.string.clone()).unwrap())
+        Some(self.string.clone())
+    }
+}
+
+impl<M: AsRef<[u8]>> Iterator for OwnedPfcBlockIterator<M> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.next_bytes()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
     }
 }
 
@@ -182,8 +234,18 @@ impl<M: AsRef<[u8]>> PfcBlock<M> {
     }
 
     pub fn get(&self, index: usize) -> Option<String> {
+        self.get_bytes(index)
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    pub fn get_bytes(&self, index: usize) -> Option<Vec<u8>> {
         if index < self.n_strings {
-            self.strings().nth(index)
+            let mut strings = self.strings();
+            for _ in 0..index {
+                strings.next_bytes();
+            }
+
+            strings.next_bytes()
         } else {
             None
         }
@@ -195,9 +257,32 @@ impl<M: AsRef<[u8]>> PfcBlock<M> {
     }
 }
 
+/// Aggregate statistics about the blocks of a `PfcDict`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStats {
+    /// the amount of blocks in the dict
+    pub num_blocks: usize,
+    /// the amount of strings in the dict
+    pub num_entries: u64,
+    /// the amount of strings in every block but the last
+    pub entries_per_block: usize,
+    /// the amount of strings in the last block, which may be incomplete
+    pub last_block_entries: usize,
+    /// the average length of the prefix shared with the previous
+    /// string, over all strings that aren't the head of their block
+    pub avg_shared_prefix_len: f64,
+}
+
+/// A dictionary of sorted strings, stored in plain front-coded blocks.
+///
+/// When the backing maps are shared (as with `SharedVec` and
+/// `SharedMmap`), cloning a dict only bumps reference counts, so a
+/// clone can be handed to every concurrent reader. Readers never
+/// mutate the dict, so such a dict is `Send + Sync`.
 #[derive(Clone)]
 pub struct PfcDict<M: AsRef<[u8]>> {
     n_strings: u64,
+    block_size: usize,
     block_offsets: LogArray<M>,
     blocks: M,
 }
@@ -208,56 +293,85 @@ pub struct PfcDictIterator<'a, M: AsRef<[u8]>> {
     block: Option<OwnedPfcBlockIterator<&'a [u8]>>,
 }
 
-impl<'a, M: AsRef<[u8]>> Iterator for PfcDictIterator<'a, M> {
-    type Item = String;
-
-    fn next(&mut self) -> Option<String> {
-        if self.block_index >= self.dict.block_offsets.len() + 1 {
+impl<'a, M: AsRef<[u8]>> PfcDictIterator<'a, M> {
+    /// Returns the next string in this dict as raw bytes, without checking that it is valid UTF-8.
+    pub fn next_bytes(&mut self) -> Option<Vec<u8>> {
+        if self.block_index * self.dict.block_size >= self.dict.n_strings as usize {
             return None;
         } else if self.block.is_none() {
-            let block_offset = if self.block_index == 0 {
-                0
-            } else {
-                self.dict.block_offsets.entry(self.block_index - 1)
-            } as usize;
-            let remainder = self.dict.n_strings as usize - self.block_index * BLOCK_SIZE;
-            if remainder >= BLOCK_SIZE {
-                self.block = Some(
-                    PfcBlock::parse(&self.dict.blocks.as_ref()[block_offset..])
-                        .unwrap()
-                        .into_strings(),
-                );
-            } else {
-                self.block = Some(
-                    PfcBlock::parse_incomplete(
-                        &self.dict.blocks.as_ref()[block_offset..],
-                        remainder,
-                    )
-                    .unwrap()
-                    .into_strings(),
-                );
-            }
+            self.block = Some(self.dict.block(self.block_index).into_strings());
         }
 
-        match self.block.as_mut().unwrap().next() {
+        match self.block.as_mut().unwrap().next_bytes() {
             None => {
                 self.block_index += 1;
                 self.block = None;
-                self.next()
+                self.next_bytes()
             }
             Some(s) => Some(s),
         }
     }
 }
 
+impl<'a, M: AsRef<[u8]>> Iterator for PfcDictIterator<'a, M> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.next_bytes()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+}
+
+// unlike the borrowed iterator, this one cannot hold on to a block
+// iterator that points into its own dict, so each block is decoded in
+// full as soon as it is reached.
+pub struct OwnedPfcDictIterator<M: AsRef<[u8]>> {
+    dict: PfcDict<M>,
+    block_index: usize,
+    block: std::vec::IntoIter<Vec<u8>>,
+}
+
+impl<M: AsRef<[u8]>> OwnedPfcDictIterator<M> {
+    /// Returns the next string in this dict as raw bytes, without checking that it is valid UTF-8.
+    pub fn next_bytes(&mut self) -> Option<Vec<u8>> {
+        loop {
+            if let Some(s) = self.block.next() {
+                return Some(s);
+            }
+
+            if self.block_index * self.dict.block_size >= self.dict.n_strings as usize {
+                return None;
+            }
+
+            let block = self.dict.block(self.block_index);
+            let mut strings = block.strings();
+            let strings: Vec<Vec<u8>> = iter::from_fn(|| strings.next_bytes()).collect();
+            self.block = strings.into_iter();
+            self.block_index += 1;
+        }
+    }
+}
+
+impl<M: AsRef<[u8]>> Iterator for OwnedPfcDictIterator<M> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        self.next_bytes()
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+}
+
 impl<M: AsRef<[u8]>> PfcDict<M> {
     pub fn parse(blocks: M, offsets: M) -> Result<PfcDict<M>, PfcError> {
-        let n_strings = BigEndian::read_u64(&blocks.as_ref()[blocks.as_ref().len() - 8..]);
+        let (n_strings, block_size) = decode_trailer(BigEndian::read_u64(
+            &blocks.as_ref()[blocks.as_ref().len() - 8..],
+        ));
 
         let block_offsets = LogArray::parse(offsets)?;
 
         Ok(PfcDict {
             n_strings: n_strings,
+            block_size,
             block_offsets: block_offsets,
             blocks: blocks,
         })
@@ -268,23 +382,43 @@ impl<M: AsRef<[u8]>> PfcDict<M> {
     }
 
     pub fn get(&self, ix: usize) -> Option<String> {
+        self.get_bytes(ix)
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    /// Returns the string at the given index as raw bytes, without checking that it is valid UTF-8.
+    pub fn get_bytes(&self, ix: usize) -> Option<Vec<u8>> {
         if (ix as u64) < self.n_strings {
-            let block_index = ix / BLOCK_SIZE;
+            let block_index = ix / self.block_size;
             let block_offset = if block_index == 0 {
                 0
             } else {
                 self.block_offsets.entry(block_index - 1)
             };
-            let block = PfcBlock::parse(&self.blocks.as_ref()[block_offset as usize..]).unwrap();
+            let block = PfcBlock::parse_incomplete(
+                &self.blocks.as_ref()[block_offset as usize..],
+                self.block_size,
+            )
+            .unwrap();
 
-            let index_in_block = ix % BLOCK_SIZE;
-            block.get(index_in_block)
+            let index_in_block = ix % self.block_size;
+            block.get_bytes(index_in_block)
         } else {
             None
         }
     }
 
-    pub fn id(&self, s: &str) -> Option<u64> {
+    /// Returns the block a string would have to be in, if it is in this dict at all.
+    ///
+    /// This is the last block whose head is not greater than the given
+    /// string. None is returned if the string sorts before every string
+    /// in this dict.
+    fn find_block(&self, s: &[u8]) -> Option<usize> {
+        if self.n_strings == 0 {
+            // an empty dict has no block heads to search through
+            return None;
+        }
+
         // let's binary search
         let mut min = 0;
         let mut max = self.block_offsets.len();
@@ -302,9 +436,7 @@ impl<M: AsRef<[u8]>> PfcDict<M> {
             let head_end = block_slice.iter().position(|&b| b == 0).unwrap();
             let head_slice = &block_slice[..head_end];
 
-            let head = String::from_utf8(head_slice.to_vec()).unwrap();
-
-            match s.cmp(&head) {
+            match s.cmp(head_slice) {
                 Ordering::Less => {
                     if mid == 0 {
                         // we checked the first block and determined that the string should be in the previous block, if it exists.
@@ -314,30 +446,58 @@ impl<M: AsRef<[u8]>> PfcDict<M> {
                     max = mid - 1;
                 }
                 Ordering::Greater => min = mid + 1,
-                Ordering::Equal => return Some((mid * BLOCK_SIZE) as u64), // what luck! turns out the string we were looking for was the block head
+                Ordering::Equal => return Some(mid), // what luck! turns out the string we were looking for was the block head
             }
         }
 
-        let found = max;
+        Some(max)
+    }
 
-        // we found the block the string should be part of.
-        let block_start = if found == 0 {
+    fn block(&self, block_index: usize) -> PfcBlock<&[u8]> {
+        let block_start = if block_index == 0 {
             0
         } else {
-            self.block_offsets.entry(found - 1) as usize
-        };
-        let remainder = self.n_strings as usize - (found * BLOCK_SIZE);
-        let block = if remainder >= BLOCK_SIZE {
-            PfcBlock::parse(&self.blocks.as_ref()[block_start..]).unwrap()
-        } else {
-            PfcBlock::parse_incomplete(&self.blocks.as_ref()[block_start..], remainder as usize)
-                .unwrap()
+            self.block_offsets.entry(block_index - 1) as usize
         };
+        let remainder = self.n_strings as usize - (block_index * self.block_size);
+        let n_strings = std::cmp::min(remainder, self.block_size);
+        PfcBlock::parse_incomplete(&self.blocks.as_ref()[block_start..], n_strings).unwrap()
+    }
 
+    /// Returns the id of the first string that is not less than the given string.
+    ///
+    /// If all strings in this dict are less than the given string,
+    /// the length of the dict is returned.
+    fn lower_bound(&self, s: &[u8]) -> usize {
+        match self.find_block(s) {
+            None => 0,
+            Some(found) => {
+                let block = self.block(found);
+                let mut strings = block.strings();
+                let in_block = iter::from_fn(|| strings.next_bytes())
+                    .take_while(|block_string| block_string.as_slice() < s)
+                    .count();
+
+                found * self.block_size + in_block
+            }
+        }
+    }
+
+    pub fn id(&self, s: &str) -> Option<u64> {
+        self.id_bytes(s.as_bytes())
+    }
+
+    /// Returns the id of the given byte string, which does not have to be valid UTF-8.
+    pub fn id_bytes(&self, s: &[u8]) -> Option<u64> {
+        let found = self.find_block(s)?;
+
+        // we found the block the string should be part of.
+        let block = self.block(found);
+        let mut strings = block.strings();
         let mut count = 0;
-        for block_string in block.strings() {
+        while let Some(block_string) = strings.next_bytes() {
             if block_string == s {
-                return Some((found * BLOCK_SIZE + count) as u64);
+                return Some((found * self.block_size + count) as u64);
             }
             count += 1;
         }
@@ -345,6 +505,125 @@ impl<M: AsRef<[u8]>> PfcDict<M> {
         None
     }
 
+    /// Returns the ids of many strings at once, in the order they were given.
+    ///
+    /// The queries are resolved in sorted order, so that every block of
+    /// this dict is decoded at most once, no matter how many queries
+    /// fall inside it.
+    pub fn ids(&self, strings: &[&str]) -> Vec<Option<u64>> {
+        let mut order: Vec<usize> = (0..strings.len()).collect();
+        order.sort_by_key(|&i| strings[i]);
+
+        let mut result = vec![None; strings.len()];
+        let mut current_block: Option<(usize, Vec<Vec<u8>>)> = None;
+        for i in order {
+            let s = strings[i];
+            let found = match self.find_block(s.as_bytes()) {
+                None => continue,
+                Some(found) => found,
+            };
+
+            if current_block.as_ref().map(|(index, _)| *index) != Some(found) {
+                let block = self.block(found);
+                let mut strings = block.strings();
+                current_block = Some((found, iter::from_fn(|| strings.next_bytes()).collect()));
+            }
+            let (_, block_strings) = current_block.as_ref().unwrap();
+
+            result[i] = block_strings
+                .iter()
+                .position(|block_string| block_string.as_slice() == s.as_bytes())
+                .map(|count| (found * self.block_size + count) as u64);
+        }
+
+        result
+    }
+
+    /// Returns the ids of all strings starting with the given prefix, in ascending order.
+    pub fn ids_with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = u64> + 'a {
+        let start = self.lower_bound(prefix.as_bytes());
+
+        self.bytes_from(start)
+            .take_while(move |s| s.starts_with(prefix.as_bytes()))
+            .enumerate()
+            .map(move |(count, _)| (start + count) as u64)
+    }
+
+    /// Returns the ids of all strings that equal the given string when ignoring ASCII case, in ascending order.
+    ///
+    /// Only ASCII letters are folded. Candidates are built up one
+    /// character at a time in both cases, dropping any prefix that no
+    /// string in this dict starts with, so the whole dict is never
+    /// scanned.
+    pub fn id_ci(&self, s: &str) -> Vec<u64> {
+        let mut prefixes = vec![String::new()];
+        for c in s.chars() {
+            let variants = if c.is_ascii_alphabetic() {
+                vec![c.to_ascii_uppercase(), c.to_ascii_lowercase()]
+            } else {
+                vec![c]
+            };
+
+            prefixes = prefixes
+                .iter()
+                .flat_map(|prefix| {
+                    variants.iter().map(move |&variant| {
+                        let mut extended = prefix.clone();
+                        extended.push(variant);
+                        extended
+                    })
+                })
+                .filter(|prefix| self.ids_with_prefix(prefix).next().is_some())
+                .collect();
+        }
+
+        let mut result: Vec<u64> = prefixes
+            .iter()
+            .filter_map(|candidate| self.id(candidate))
+            .collect();
+        result.sort();
+
+        result
+    }
+
+    /// Returns the first string that is not less than the given string, together with its id.
+    ///
+    /// Strings are compared as bytes. If the string found is not valid
+    /// UTF-8, invalid sequences are replaced by U+FFFD. Use
+    /// `successor_bytes` to get the string exactly as stored.
+    pub fn successor(&self, s: &str) -> Option<(u64, String)> {
+        self.successor_bytes(s.as_bytes())
+            .map(|(ix, found)| (ix, lossy_string(found)))
+    }
+
+    /// Returns the first byte string that is not less than the given byte string, together with its id.
+    pub fn successor_bytes(&self, s: &[u8]) -> Option<(u64, Vec<u8>)> {
+        let ix = self.lower_bound(s);
+
+        self.get_bytes(ix).map(|found| (ix as u64, found))
+    }
+
+    /// Returns the last string that is not greater than the given string, together with its id.
+    ///
+    /// Strings are compared as bytes. If the string found is not valid
+    /// UTF-8, invalid sequences are replaced by U+FFFD. Use
+    /// `predecessor_bytes` to get the string exactly as stored.
+    pub fn predecessor(&self, s: &str) -> Option<(u64, String)> {
+        self.predecessor_bytes(s.as_bytes())
+            .map(|(ix, found)| (ix, lossy_string(found)))
+    }
+
+    /// Returns the last byte string that is not greater than the given byte string, together with its id.
+    pub fn predecessor_bytes(&self, s: &[u8]) -> Option<(u64, Vec<u8>)> {
+        let ix = self.lower_bound(s);
+
+        match self.get_bytes(ix) {
+            Some(found) if found == s => Some((ix as u64, found)),
+            _ if ix == 0 => None,
+            _ => self.get_bytes(ix - 1).map(|found| ((ix - 1) as u64, found)),
+        }
+    }
+
     pub fn strings(&self) -> PfcDictIterator<M> {
         PfcDictIterator {
             dict: &self,
@@ -352,6 +631,111 @@ impl<M: AsRef<[u8]>> PfcDict<M> {
             block: None,
         }
     }
+
+    pub fn into_strings(self) -> OwnedPfcDictIterator<M> {
+        OwnedPfcDictIterator {
+            dict: self,
+            block_index: 0,
+            block: Vec::new().into_iter(),
+        }
+    }
+
+    /// Iterates over all strings in this dict as raw bytes, which do not have to be valid UTF-8.
+    pub fn strings_bytes(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        self.bytes_from(0)
+    }
+
+    /// Iterates over all strings in this dict as raw bytes, consuming the dict.
+    pub fn into_bytes(self) -> impl Iterator<Item = Vec<u8>> {
+        let mut strings = self.into_strings();
+        iter::from_fn(move || strings.next_bytes())
+    }
+
+    fn bytes_from(&self, ix: usize) -> impl Iterator<Item = Vec<u8>> + '_ {
+        let mut strings = PfcDictIterator {
+            dict: self,
+            block_index: ix / self.block_size,
+            block: None,
+        };
+
+        iter::from_fn(move || strings.next_bytes()).skip(ix % self.block_size)
+    }
+
+    /// Returns the size in bytes of the blocks and block offsets buffers together.
+    ///
+    /// For a memory-mapped dict this is the size of the mapped regions
+    /// rather than of actual heap allocations.
+    pub fn heap_size(&self) -> usize {
+        self.blocks.as_ref().len() + self.block_offsets.heap_size()
+    }
+
+    /// Collects statistics about the blocks of this dict.
+    ///
+    /// This reads the shared prefix lengths straight from the encoded
+    /// blocks, without reconstructing any strings.
+    pub fn block_stats(&self) -> BlockStats {
+        let n_strings = self.n_strings as usize;
+        let num_blocks = (0..n_strings).step_by(self.block_size).len();
+        let last_block_entries = match n_strings % self.block_size {
+            0 if n_strings != 0 => self.block_size,
+            remainder => remainder,
+        };
+
+        let mut shared_total = 0;
+        let mut shared_count = 0;
+        for block_index in 0..num_blocks {
+            let block = self.block(block_index);
+            let data = block.encoded_strings;
+            let mut pos = block.head().len() + 1;
+            for _ in 1..block.n_strings {
+                let (common, common_len) =
+                    vbyte::decode(&data[pos..]).expect("encoding error in self-managed data");
+                pos += common_len;
+                pos += data[pos..].iter().position(|&b| b == 0).unwrap() + 1;
+
+                shared_total += common;
+                shared_count += 1;
+            }
+        }
+
+        BlockStats {
+            num_blocks,
+            num_entries: self.n_strings,
+            entries_per_block: self.block_size,
+            last_block_entries,
+            avg_shared_prefix_len: if shared_count == 0 {
+                0.0
+            } else {
+                shared_total as f64 / shared_count as f64
+            },
+        }
+    }
+
+    /// Iterates over all entries of this dict in descending id order.
+    ///
+    /// Front coding can only be decoded forwards, so each block is
+    /// decoded in full before its entries are returned in reverse. This
+    /// costs one block worth of strings in memory, and is otherwise as
+    /// cheap as forward iteration.
+    ///
+    /// Entries that are not valid UTF-8 have invalid sequences replaced
+    /// by U+FFFD.
+    pub fn entries_rev(&self) -> impl Iterator<Item = (u64, String)> + '_ {
+        (0..self.n_strings as usize)
+            .step_by(self.block_size)
+            .rev()
+            .flat_map(move |block_start| {
+                let block_index = block_start / self.block_size;
+                let block = self.block(block_index);
+                let mut strings = block.strings();
+                let strings: Vec<Vec<u8>> = iter::from_fn(|| strings.next_bytes()).collect();
+                strings
+                    .into_iter()
+                    .enumerate()
+                    .rev()
+                    .map(move |(i, s)| ((block_start + i) as u64, lossy_string(s)))
+            })
+    }
 }
 
 pub struct PfcDictFileBuilder<W: tokio::io::AsyncWrite + Send> {
@@ -359,6 +743,8 @@ pub struct PfcDictFileBuilder<W: tokio::io::AsyncWrite + Send> {
     pfc_blocks_file: W,
     /// the file that this builder writes the block offsets to
     pfc_block_offsets_file: W,
+    /// the amount of strings in each block
+    block_size: usize,
     /// the amount of strings in this dict so far
     count: usize,
     /// the size in bytes of the pfc data structure so far
@@ -369,9 +755,30 @@ pub struct PfcDictFileBuilder<W: tokio::io::AsyncWrite + Send> {
 
 impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
     pub fn new(pfc_blocks_file: W, pfc_block_offsets_file: W) -> PfcDictFileBuilder<W> {
+        Self::with_block_size(pfc_blocks_file, pfc_block_offsets_file, BLOCK_SIZE)
+    }
+
+    /// Create a builder that puts the given amount of strings in each block.
+    ///
+    /// Larger blocks compress better, but make lookups decode more
+    /// strings. The block size is recorded in the dictionary, so
+    /// `PfcDict::parse` picks it up again.
+    ///
+    /// Panics if the block size is not between 1 and 255.
+    pub fn with_block_size(
+        pfc_blocks_file: W,
+        pfc_block_offsets_file: W,
+        block_size: usize,
+    ) -> PfcDictFileBuilder<W> {
+        assert!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            "block size must be between 1 and {}",
+            MAX_BLOCK_SIZE
+        );
         PfcDictFileBuilder {
             pfc_blocks_file,
             pfc_block_offsets_file,
+            block_size,
             count: 0,
             size: 0,
             last: None,
@@ -382,12 +789,60 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
         self,
         s: &str,
     ) -> impl Future<Item = (u64, PfcDictFileBuilder<W>), Error = std::io::Error> + Send {
+        self.add_bytes(s.as_bytes())
+    }
+
+    /// Add a byte string which does not have to be valid UTF-8.
+    ///
+    /// As strings are stored nul-terminated, this fails with
+    /// `PfcError::NulByte` (as an `InvalidInput` io error) if the given
+    /// bytes contain a nul byte.
+    ///
+    /// Like `add`, this fails with `PfcError::OutOfOrder` (as an
+    /// `InvalidInput` io error) if the string is not greater than the
+    /// string added before it.
+    pub fn add_bytes(
+        self,
+        s: &[u8],
+    ) -> impl Future<Item = (u64, PfcDictFileBuilder<W>), Error = std::io::Error> + Send {
+        if s.contains(&0) {
+            future::Either::A(future::err(PfcError::NulByte.into()))
+        } else if self
+            .last
+            .as_ref()
+            .map(|last| last.as_slice() >= s)
+            .unwrap_or(false)
+        {
+            future::Either::A(future::err(PfcError::OutOfOrder.into()))
+        } else {
+            future::Either::B(self.add_bytes_unchecked(s))
+        }
+    }
+
+    /// Add a string without checking that it comes after the string added before it.
+    ///
+    /// Adding strings out of order results in a dictionary that gives
+    /// wrong answers, so this is only for callers that already
+    /// guarantee their input is sorted and free of duplicates. The
+    /// string is not checked for nul bytes either.
+    pub fn add_sorted_unchecked(
+        self,
+        s: &str,
+    ) -> impl Future<Item = (u64, PfcDictFileBuilder<W>), Error = std::io::Error> + Send {
+        self.add_bytes_unchecked(s.as_bytes())
+    }
+
+    fn add_bytes_unchecked(
+        self,
+        s: &[u8],
+    ) -> impl Future<Item = (u64, PfcDictFileBuilder<W>), Error = std::io::Error> + Send {
+        let block_size = self.block_size;
         let count = self.count;
         let size = self.size;
         let mut index = self.index;
 
-        let bytes = s.as_bytes().to_vec();
-        if self.count % BLOCK_SIZE == 0 {
+        let bytes = s.to_vec();
+        if self.count % block_size == 0 {
             if self.count != 0 {
                 // this is the start of a block, but not the start of the first block
                 // we need to store an index
@@ -402,6 +857,7 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
                             PfcDictFileBuilder {
                                 pfc_blocks_file: f,
                                 pfc_block_offsets_file,
+                                block_size,
                                 count: count + 1,
                                 size: size + len,
                                 last: Some(bytes),
@@ -412,9 +868,8 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
                 ),
             )
         } else {
-            let s_bytes = s.as_bytes();
-            let common = find_common_prefix(&self.last.unwrap(), s_bytes);
-            let postfix = s_bytes[common..].to_vec();
+            let common = find_common_prefix(&self.last.unwrap(), s);
+            let postfix = s[common..].to_vec();
             let pfc_block_offsets_file = self.pfc_block_offsets_file;
             future::Either::B(
                 vbyte::write_async(self.pfc_blocks_file, common as u64).and_then(
@@ -426,6 +881,7 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
                                     PfcDictFileBuilder {
                                         pfc_blocks_file,
                                         pfc_block_offsets_file,
+                                        block_size,
                                         count: count + 1,
                                         size: size + common_len + slice_len,
                                         last: Some(bytes),
@@ -464,7 +920,7 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
             64 - self.index[self.index.len() - 1].leading_zeros()
         };
         let builder = LogArrayFileBuilder::new(self.pfc_block_offsets_file, width as u8);
-        let count = self.count;
+        let trailer = encode_trailer(self.count as u64, self.block_size);
 
         let write_offsets = builder
             .push_all(futures::stream::iter_ok(self.index))
@@ -473,7 +929,7 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
         let finalize_blocks = write_padding(self.pfc_blocks_file, self.size, 8)
             .and_then(move |(w, _n_pad)| {
                 let mut bytes = vec![0; 8];
-                BigEndian::write_u64(&mut bytes, count as u64);
+                BigEndian::write_u64(&mut bytes, trailer);
                 tokio::io::write_all(w, bytes)
             })
             .and_then(|(w, _)| tokio::io::flush(w));
@@ -482,11 +938,132 @@ impl<W: 'static + tokio::io::AsyncWrite + Send> PfcDictFileBuilder<W> {
     }
 }
 
+/// Build a dictionary from a stream of strings that is already sorted.
+///
+/// No sorting or buffering takes place. The order is still checked,
+/// and a string that is not greater than its predecessor fails the
+/// build with `PfcError::OutOfOrder` (as an `InvalidInput` io error).
+/// Returns the amount of strings in the dictionary.
+pub fn build_dict_from_sorted_stream<
+    F: 'static + FileLoad + FileStore,
+    S: 'static + Stream<Item = String, Error = std::io::Error> + Send,
+>(
+    sorted: S,
+    files: DictionaryFiles<F>,
+) -> impl Future<Item = u64, Error = std::io::Error> + Send {
+    let builder = PfcDictFileBuilder::new(
+        files.blocks_file.open_write_truncate(),
+        files.offsets_file.open_write_truncate(),
+    );
+
+    sorted
+        .fold(builder, |builder, s| {
+            builder.add(&s).map(|(_, builder)| builder)
+        })
+        .and_then(|builder| {
+            let count = builder.count as u64;
+            builder.finalize().map(move |_| count)
+        })
+}
+
+/// A single difference between two dictionaries.
+///
+/// `dictionary_diff` produces these with `String`s, and
+/// `dictionary_diff_bytes` with the raw bytes as stored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DictDiff<T = String> {
+    Added(T),
+    Removed(T),
+}
+
+impl DictDiff<Vec<u8>> {
+    fn into_lossy_string(self) -> DictDiff {
+        match self {
+            DictDiff::Added(s) => DictDiff::Added(lossy_string(s)),
+            DictDiff::Removed(s) => DictDiff::Removed(lossy_string(s)),
+        }
+    }
+}
+
+// converts a string read from a dict for returning through an API that
+// promises a `String`, replacing invalid UTF-8 rather than panicking.
+fn lossy_string(bytes: Vec<u8>) -> String {
+    String::from_utf8(bytes).unwrap_or_else(|e| String::from_utf8_lossy(e.as_bytes()).into_owned())
+}
+
+fn diff_sorted_strings<I1: Iterator<Item = Vec<u8>>, I2: Iterator<Item = Vec<u8>>>(
+    mut old: Peekable<I1>,
+    mut new: Peekable<I2>,
+) -> impl Iterator<Item = DictDiff<Vec<u8>>> {
+    iter::from_fn(move || loop {
+        let order = match (old.peek(), new.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(o), Some(n)) => o.cmp(n),
+        };
+
+        match order {
+            Ordering::Less => return old.next().map(DictDiff::Removed),
+            Ordering::Greater => return new.next().map(DictDiff::Added),
+            Ordering::Equal => {
+                // present in both, so not part of the diff
+                old.next();
+                new.next();
+            }
+        }
+    })
+}
+
+/// Map the files of a dictionary and parse them into a `PfcDict`.
+pub fn load_dict<F: 'static + FileLoad + FileStore>(
+    files: &DictionaryFiles<F>,
+) -> impl Future<Item = PfcDict<F::Map>, Error = std::io::Error> + Send {
+    files.map_all().and_then(|maps| {
+        PfcDict::parse(maps.blocks_map, maps.offsets_map)
+            .map_err(|e| -> std::io::Error { e.into() })
+    })
+}
+
+/// Stream the strings that were added and removed going from the old dictionary to the new one.
+///
+/// Both dictionaries are walked once in sorted order, so this takes
+/// time linear in their combined size. Differences are produced in
+/// sorted order.
+///
+/// Strings are compared as bytes. Strings that are not valid UTF-8
+/// have invalid sequences replaced by U+FFFD, so two different
+/// strings may show up the same. Use `dictionary_diff_bytes` to get
+/// the strings exactly as stored.
+pub fn dictionary_diff<F: 'static + FileLoad + FileStore>(
+    old: DictionaryFiles<F>,
+    new: DictionaryFiles<F>,
+) -> impl Stream<Item = DictDiff, Error = std::io::Error> + Send {
+    dictionary_diff_bytes(old, new).map(DictDiff::into_lossy_string)
+}
+
+/// Stream the byte strings that were added and removed going from the old dictionary to the new one.
+///
+/// Like `dictionary_diff`, but without converting to `String`.
+pub fn dictionary_diff_bytes<F: 'static + FileLoad + FileStore>(
+    old: DictionaryFiles<F>,
+    new: DictionaryFiles<F>,
+) -> impl Stream<Item = DictDiff<Vec<u8>>, Error = std::io::Error> + Send {
+    load_dict(&old)
+        .join(load_dict(&new))
+        .map(|(old, new)| {
+            stream::iter_ok(diff_sorted_strings(
+                old.into_bytes().peekable(),
+                new.into_bytes().peekable(),
+            ))
+        })
+        .flatten_stream()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::storage::memory::*;
-    use crate::storage::*;
 
     #[test]
     fn can_create_pfc_dict_small() {
@@ -557,10 +1134,10 @@ mod tests {
         let contents = vec![
             "aaaaa",
             "aaaaaaaaaa",
-            "arf",
             "aaaabbbbbb",
             "abcdefghijk",
             "addeeerafa",
+            "arf",
             "bapofsi",
             "barf",
             "berf",
@@ -589,7 +1166,7 @@ mod tests {
             PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
 
         assert_eq!(Some(0), dict.id("aaaaa"));
-        assert_eq!(Some(2), dict.id("arf"));
+        assert_eq!(Some(5), dict.id("arf"));
         assert_eq!(Some(7), dict.id("barf"));
         assert_eq!(Some(8), dict.id("berf"));
         assert_eq!(Some(15), dict.id("frumps framps fremps"));
@@ -605,10 +1182,10 @@ mod tests {
         let contents = vec![
             "aaaaa",
             "aaaaaaaaaa",
-            "arf",
             "aaaabbbbbb",
             "abcdefghijk",
             "addeeerafa",
+            "arf",
             "bapofsi",
             "barf",
             "berf",
@@ -639,4 +1216,651 @@ mod tests {
         let result: Vec<String> = dict.strings().collect();
         assert_eq!(contents, result);
     }
+
+    fn build_dict(contents: Vec<String>) -> PfcDict<SharedVec> {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+
+        builder
+            .add_all(contents.into_iter())
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn empty_dict_has_no_entries() {
+        let dict = build_dict(Vec::new());
+
+        assert_eq!(0, dict.len());
+        assert_eq!(None, dict.get(0));
+        assert_eq!(None, dict.id(""));
+        assert_eq!(None, dict.id("a"));
+        assert_eq!(0, dict.strings().count());
+    }
+
+    #[test]
+    fn get_at_block_boundaries() {
+        let contents: Vec<String> = (0..16).map(|i| format!("{:02}", i)).collect();
+        let dict = build_dict(contents.clone());
+
+        assert_eq!(Some("07".to_string()), dict.get(7));
+        assert_eq!(Some("08".to_string()), dict.get(8));
+        assert_eq!(Some("15".to_string()), dict.get(15));
+        assert_eq!(None, dict.get(16));
+        assert_eq!(Some(8), dict.id("08"));
+        assert_eq!(Some(15), dict.id("15"));
+        assert_eq!(None, dict.id("16"));
+        assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn retrieve_ids_with_prefix() {
+        let contents: Vec<String> = vec![
+            "aaaaa", "aabbb", "abc", "abcd", "abcde", "abd", "b", "ba", "bab", "babbage", "bb", "c",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+        let dict = build_dict(contents);
+
+        assert_eq!(
+            vec![2, 3, 4],
+            dict.ids_with_prefix("abc").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![7, 8, 9],
+            dict.ids_with_prefix("ba").collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![6, 7, 8, 9, 10],
+            dict.ids_with_prefix("b").collect::<Vec<_>>()
+        );
+        assert_eq!(vec![11], dict.ids_with_prefix("c").collect::<Vec<_>>());
+        assert_eq!(12, dict.ids_with_prefix("").count());
+        assert_eq!(0, dict.ids_with_prefix("abf").count());
+        assert_eq!(0, dict.ids_with_prefix("0").count());
+        assert_eq!(0, dict.ids_with_prefix("d").count());
+    }
+
+    #[test]
+    fn retrieve_successor_and_predecessor() {
+        let contents: Vec<String> = (0..20).map(|i| format!("{:02}", i * 2)).collect();
+        let dict = build_dict(contents);
+
+        assert_eq!(Some((0, "00".to_string())), dict.successor(""));
+        assert_eq!(Some((3, "06".to_string())), dict.successor("06"));
+        assert_eq!(Some((4, "08".to_string())), dict.successor("07"));
+        assert_eq!(Some((8, "16".to_string())), dict.successor("15"));
+        assert_eq!(Some((19, "38".to_string())), dict.successor("37"));
+        assert_eq!(None, dict.successor("39"));
+
+        assert_eq!(None, dict.predecessor(""));
+        assert_eq!(Some((3, "06".to_string())), dict.predecessor("06"));
+        assert_eq!(Some((3, "06".to_string())), dict.predecessor("07"));
+        assert_eq!(Some((7, "14".to_string())), dict.predecessor("15"));
+        assert_eq!(Some((19, "38".to_string())), dict.predecessor("39"));
+    }
+
+    #[test]
+    fn retrieve_non_utf8_byte_strings() {
+        let contents: Vec<Vec<u8>> = vec![
+            vec![b'a'],
+            vec![b'a', 0xc3, 0x28],
+            vec![b'a', 0xff],
+            vec![0x80],
+            vec![0xc3, 0x28, 0x01],
+            vec![0xfe, 0xfe],
+            vec![0xff],
+            vec![0xff, 0xfe],
+            vec![0xff, 0xff],
+            vec![0xff, 0xff, 0xff],
+        ];
+
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let mut builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        for bytes in contents.iter() {
+            builder = builder.add_bytes(bytes).wait().unwrap().1;
+        }
+        builder.finalize().wait().unwrap();
+
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+
+        for (ix, bytes) in contents.iter().enumerate() {
+            assert_eq!(Some(bytes.clone()), dict.get_bytes(ix));
+            assert_eq!(Some(ix as u64), dict.id_bytes(bytes));
+        }
+        assert_eq!(None, dict.get_bytes(contents.len()));
+        assert_eq!(None, dict.id_bytes(&[0xc3]));
+        assert_eq!(Some(0), dict.id("a"));
+    }
+
+    #[test]
+    fn retrieve_ids_ignoring_case() {
+        let contents: Vec<String> = vec![
+            "1-aBC",
+            "1-abc",
+            "ABC",
+            "Abc",
+            "Abd",
+            "aBc",
+            "abc",
+            "abcd",
+            "xyz",
+            "\u{e9}t\u{e9}",
+        ]
+        .into_iter()
+        .map(|s| s.to_string())
+        .collect();
+        let dict = build_dict(contents);
+
+        assert_eq!(vec![2, 3, 5, 6], dict.id_ci("abc"));
+        assert_eq!(vec![2, 3, 5, 6], dict.id_ci("ABC"));
+        assert_eq!(vec![0, 1], dict.id_ci("1-ABC"));
+        assert_eq!(vec![4], dict.id_ci("abd"));
+        assert_eq!(vec![9], dict.id_ci("\u{e9}T\u{e9}"));
+        assert_eq!(Vec::<u64>::new(), dict.id_ci("\u{c9}t\u{e9}"));
+        assert_eq!(Vec::<u64>::new(), dict.id_ci("ab"));
+        assert_eq!(Vec::<u64>::new(), dict.id_ci("abe"));
+    }
+
+    #[test]
+    fn iterate_owned_strings() {
+        let contents: Vec<String> = (0..20).map(|i| format!("{:02}", i)).collect();
+        let dict = build_dict(contents.clone());
+
+        assert_eq!(contents, dict.into_strings().collect::<Vec<_>>());
+        assert_eq!(0, build_dict(Vec::new()).into_strings().count());
+    }
+
+    fn build_dict_files(contents: Vec<&'static str>) -> DictionaryFiles<MemoryBackedStore> {
+        let files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        let builder = PfcDictFileBuilder::new(
+            files.blocks_file.open_write(),
+            files.offsets_file.open_write(),
+        );
+
+        builder
+            .add_all(contents.into_iter().map(|s| s.to_string()))
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        files
+    }
+
+    #[test]
+    fn diff_two_dictionaries() {
+        let old = build_dict_files(vec![
+            "aaaaa", "bbbbb", "ccccc", "ddddd", "eeeee", "fffff", "ggggg", "hhhhh", "iiiii",
+        ]);
+        let new = build_dict_files(vec![
+            "0", "aaaaa", "ccccc", "cd", "ddddd", "fffff", "ggggg", "hhhhh", "zzzzz",
+        ]);
+
+        let diff: Vec<DictDiff> = dictionary_diff(old, new).collect().wait().unwrap();
+
+        assert_eq!(
+            vec![
+                DictDiff::Added("0".to_string()),
+                DictDiff::Removed("bbbbb".to_string()),
+                DictDiff::Added("cd".to_string()),
+                DictDiff::Removed("eeeee".to_string()),
+                DictDiff::Removed("iiiii".to_string()),
+                DictDiff::Added("zzzzz".to_string()),
+            ],
+            diff
+        );
+    }
+
+    #[test]
+    fn diff_against_empty_dictionary() {
+        let old = build_dict_files(Vec::new());
+        let new = build_dict_files(vec!["a", "b"]);
+
+        let diff: Vec<DictDiff> = dictionary_diff(old.clone(), new.clone())
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            vec![
+                DictDiff::Added("a".to_string()),
+                DictDiff::Added("b".to_string())
+            ],
+            diff
+        );
+
+        let diff: Vec<DictDiff> = dictionary_diff(new, old).collect().wait().unwrap();
+        assert_eq!(
+            vec![
+                DictDiff::Removed("a".to_string()),
+                DictDiff::Removed("b".to_string())
+            ],
+            diff
+        );
+    }
+
+    #[test]
+    fn retrieve_many_ids_at_once() {
+        let contents: Vec<String> = (0..30).map(|i| format!("{:03}", i * 3)).collect();
+        let dict = build_dict(contents);
+
+        let queries = vec!["087", "000", "001", "045", "", "999", "042", "045", "003"];
+        let expected: Vec<Option<u64>> = queries.iter().map(|q| dict.id(q)).collect();
+
+        assert_eq!(
+            vec![
+                Some(29),
+                Some(0),
+                None,
+                Some(15),
+                None,
+                None,
+                Some(14),
+                Some(15),
+                Some(1)
+            ],
+            expected
+        );
+        assert_eq!(expected, dict.ids(&queries));
+        assert_eq!(Vec::<Option<u64>>::new(), dict.ids(&[]));
+    }
+
+    #[test]
+    fn retrieve_many_ids_next_to_non_utf8_strings() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        let (_, builder) = builder.add_bytes(b"a").wait().unwrap();
+        let (_, builder) = builder.add_bytes(&[b'b', 0xff]).wait().unwrap();
+        let (_, builder) = builder.add_bytes(b"c").wait().unwrap();
+        builder.finalize().wait().unwrap();
+
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+
+        assert_eq!(vec![Some(2), None, Some(0)], dict.ids(&["c", "b", "a"]));
+    }
+
+    #[test]
+    fn lookups_skip_over_non_utf8_strings() {
+        let files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        let builder = PfcDictFileBuilder::new(
+            files.blocks_file.open_write(),
+            files.offsets_file.open_write(),
+        );
+        let (_, builder) = builder.add_bytes(b"a").wait().unwrap();
+        let (_, builder) = builder.add_bytes(&[b'b', 0xff]).wait().unwrap();
+        let (_, builder) = builder.add_bytes(b"c").wait().unwrap();
+        builder.finalize().wait().unwrap();
+
+        let dict = load_dict(&files).wait().unwrap();
+
+        assert_eq!(vec![2], dict.ids_with_prefix("c").collect::<Vec<_>>());
+        assert_eq!(vec![1], dict.ids_with_prefix("b").collect::<Vec<_>>());
+        assert_eq!(vec![2], dict.id_ci("C"));
+        assert_eq!(Some((1, "b\u{fffd}".to_string())), dict.successor("bz"));
+        assert_eq!(Some((2, "c".to_string())), dict.successor("c"));
+        assert_eq!(Some((0, "a".to_string())), dict.predecessor("bz"));
+        assert_eq!(Some((2, "c".to_string())), dict.predecessor("cz"));
+        assert_eq!(vec![Some(2), None, Some(0)], dict.ids(&["c", "b", "a"]));
+
+        let expected = vec![b"a".to_vec(), vec![b'b', 0xff], b"c".to_vec()];
+        assert_eq!(expected, dict.strings_bytes().collect::<Vec<_>>());
+        assert_eq!(expected, dict.into_bytes().collect::<Vec<_>>());
+
+        let diff: Vec<DictDiff> = dictionary_diff(files, build_dict_files(vec!["a", "c", "d"]))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            vec![
+                DictDiff::Removed("b\u{fffd}".to_string()),
+                DictDiff::Added("d".to_string())
+            ],
+            diff
+        );
+    }
+
+    #[test]
+    fn shared_dicts_can_be_cloned_across_threads() {
+        fn assert_shareable<T: Clone + Send + Sync>() {}
+        assert_shareable::<PfcDict<SharedVec>>();
+        assert_shareable::<PfcDict<crate::storage::directory::SharedMmap>>();
+
+        let contents: Vec<String> = (0..20).map(|i| format!("{:02}", i)).collect();
+        let dict = build_dict(contents);
+
+        let handles: Vec<_> = (0..4)
+            .map(|t| {
+                let dict = dict.clone();
+                std::thread::spawn(move || {
+                    (0..20)
+                        .filter(|i| i % 4 == t)
+                        .all(|i| dict.id(&format!("{:02}", i)) == Some(i as u64))
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            assert!(handle.join().unwrap());
+        }
+    }
+
+    #[test]
+    fn iterate_entries_in_reverse() {
+        let contents: Vec<String> = (0..19).map(|i| format!("{:02}", i)).collect();
+        let dict = build_dict(contents.clone());
+
+        let expected: Vec<(u64, String)> = contents
+            .into_iter()
+            .enumerate()
+            .map(|(i, s)| (i as u64, s))
+            .rev()
+            .collect();
+        assert_eq!(expected, dict.entries_rev().collect::<Vec<_>>());
+
+        let empty = build_dict(Vec::new());
+        assert_eq!(0, empty.entries_rev().count());
+
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        let (_, builder) = builder.add_bytes(b"a").wait().unwrap();
+        let (_, builder) = builder.add_bytes(&[b'b', 0xff]).wait().unwrap();
+        builder.finalize().wait().unwrap();
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+        assert_eq!(
+            vec![(1, "b\u{fffd}".to_string()), (0, "a".to_string())],
+            dict.entries_rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn collect_block_stats() {
+        // within a block, every string after the first shares all but its last character
+        let contents: Vec<String> = (0..20).map(|i| format!("prefix{:02}", i)).collect();
+        let dict = build_dict(contents);
+
+        let stats = dict.block_stats();
+        assert_eq!(3, stats.num_blocks);
+        assert_eq!(20, stats.num_entries);
+        assert_eq!(8, stats.entries_per_block);
+        assert_eq!(4, stats.last_block_entries);
+
+        // "prefix09" -> "prefix10" only shares "prefix"
+        let expected = (7 * 7 + (6 * 7 + 6) + 3 * 7) as f64 / 17.0;
+        assert_eq!(expected, stats.avg_shared_prefix_len);
+    }
+
+    #[test]
+    fn collect_block_stats_of_empty_dict() {
+        let dict = build_dict(Vec::new());
+        assert_eq!(
+            BlockStats {
+                num_blocks: 0,
+                num_entries: 0,
+                entries_per_block: 8,
+                last_block_entries: 0,
+                avg_shared_prefix_len: 0.0,
+            },
+            dict.block_stats()
+        );
+    }
+
+    #[test]
+    fn heap_size_covers_blocks_and_offsets() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        let contents: Vec<String> = (0..20).map(|i| format!("{:02}", i)).collect();
+        builder
+            .add_all(contents.into_iter())
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        let blocks_size = blocks.size();
+        let offsets_size = offsets.size();
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+
+        assert_eq!(blocks_size + offsets_size, dict.heap_size());
+    }
+
+    #[test]
+    fn build_dict_from_sorted_strings() {
+        let files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        let contents: Vec<String> = (0..20).map(|i| format!("{:02}", i)).collect();
+
+        let count = build_dict_from_sorted_stream(stream::iter_ok(contents.clone()), files.clone())
+            .wait()
+            .unwrap();
+        assert_eq!(20, count);
+
+        let maps = files.map_all().wait().unwrap();
+        let dict = PfcDict::parse(maps.blocks_map, maps.offsets_map).unwrap();
+        assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn build_dict_into_reused_files() {
+        let files = build_dict_files(vec!["aaaaaaaa", "bbbbbbbb", "cccccccc", "dddddddd"]);
+        let contents = vec!["x".to_string()];
+
+        build_dict_from_sorted_stream(stream::iter_ok(contents.clone()), files.clone())
+            .wait()
+            .unwrap();
+
+        let dict = load_dict(&files).wait().unwrap();
+        assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn build_dict_from_unsorted_strings_fails() {
+        let files = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        let contents = vec!["aaa".to_string(), "ccc".to_string(), "bbb".to_string()];
+
+        let err = build_dict_from_sorted_stream(stream::iter_ok(contents), files)
+            .wait()
+            .unwrap_err();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn diff_and_neighbours_of_non_utf8_strings_as_bytes() {
+        let old = DictionaryFiles {
+            blocks_file: MemoryBackedStore::new(),
+            offsets_file: MemoryBackedStore::new(),
+        };
+        let builder =
+            PfcDictFileBuilder::new(old.blocks_file.open_write(), old.offsets_file.open_write());
+        let (_, builder) = builder.add_bytes(b"a").wait().unwrap();
+        let (_, builder) = builder.add_bytes(&[b'b', 0xfe]).wait().unwrap();
+        let (_, builder) = builder.add_bytes(&[b'b', 0xff]).wait().unwrap();
+        builder.finalize().wait().unwrap();
+
+        let dict = load_dict(&old).wait().unwrap();
+        assert_eq!(Some((1, vec![b'b', 0xfe])), dict.successor_bytes(b"bz"));
+        assert_eq!(Some((2, vec![b'b', 0xff])), dict.predecessor_bytes(b"c"));
+        assert_eq!(Some((0, b"a".to_vec())), dict.predecessor_bytes(b"bz"));
+
+        let diff: Vec<DictDiff<Vec<u8>>> = dictionary_diff_bytes(old, build_dict_files(vec!["a"]))
+            .collect()
+            .wait()
+            .unwrap();
+        assert_eq!(
+            vec![
+                DictDiff::Removed(vec![b'b', 0xfe]),
+                DictDiff::Removed(vec![b'b', 0xff])
+            ],
+            diff
+        );
+    }
+
+    #[test]
+    fn load_dict_from_files() {
+        let files = build_dict_files(vec!["aaa", "bbb", "ccc"]);
+        let dict = load_dict(&files).wait().unwrap();
+
+        assert_eq!(3, dict.len());
+        assert_eq!(Some(1), dict.id("bbb"));
+    }
+
+    #[test]
+    fn query_dicts_with_different_block_sizes() {
+        let contents: Vec<String> = (0..100).map(|i| format!("string{:03}", i * 2)).collect();
+        let dicts: Vec<_> = [8, 3, 1, 32, 255]
+            .iter()
+            .map(|&block_size| {
+                let blocks = MemoryBackedStore::new();
+                let offsets = MemoryBackedStore::new();
+                let builder = PfcDictFileBuilder::with_block_size(
+                    blocks.open_write(),
+                    offsets.open_write(),
+                    block_size,
+                );
+                builder
+                    .add_all(contents.clone().into_iter())
+                    .and_then(|(_, b)| b.finalize())
+                    .wait()
+                    .unwrap();
+
+                PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap()
+            })
+            .collect();
+
+        let default_dict = build_dict(contents.clone());
+        for dict in dicts.iter() {
+            assert_eq!(100, dict.len());
+            assert_eq!(contents, dict.strings().collect::<Vec<_>>());
+            assert_eq!(contents, dict.clone().into_strings().collect::<Vec<_>>());
+            for i in 0..100 {
+                assert_eq!(default_dict.get(i), dict.get(i));
+            }
+            for q in &[
+                "string000",
+                "string001",
+                "string100",
+                "string198",
+                "zzz",
+                "",
+            ] {
+                assert_eq!(default_dict.id(q), dict.id(q));
+                assert_eq!(default_dict.successor(q), dict.successor(q));
+                assert_eq!(default_dict.predecessor(q), dict.predecessor(q));
+            }
+            assert_eq!(
+                default_dict.ids_with_prefix("string1").collect::<Vec<_>>(),
+                dict.ids_with_prefix("string1").collect::<Vec<_>>()
+            );
+            assert_eq!(
+                default_dict.entries_rev().collect::<Vec<_>>(),
+                dict.entries_rev().collect::<Vec<_>>()
+            );
+        }
+
+        assert_eq!(13, dicts[0].block_stats().num_blocks);
+        assert_eq!(34, dicts[1].block_stats().num_blocks);
+        assert_eq!(32, dicts[3].block_stats().entries_per_block);
+    }
+
+    #[test]
+    fn default_block_size_keeps_plain_count_trailer() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        builder
+            .add_all(vec!["aaa".to_string(), "bbb".to_string()].into_iter())
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        let map = blocks.map().wait().unwrap();
+        let bytes = map.as_ref();
+        assert_eq!(2, BigEndian::read_u64(&bytes[bytes.len() - 8..]));
+    }
+
+    #[test]
+    fn adding_out_of_order_fails() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        let contents = vec!["aaa".to_string(), "ccc".to_string(), "bbb".to_string()];
+
+        let err = builder.add_all(contents.into_iter()).wait().err().unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        match err.get_ref().unwrap().downcast_ref::<PfcError>() {
+            Some(PfcError::OutOfOrder) => {}
+            _ => panic!("expected an out of order error"),
+        }
+    }
+
+    #[test]
+    fn adding_duplicate_fails() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+
+        let err = builder
+            .add("aaa")
+            .and_then(|(_, b)| b.add("aaa"))
+            .wait()
+            .err()
+            .unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+    }
+
+    #[test]
+    fn adding_bytes_with_nul_fails() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+
+        let err = builder.add_bytes(&[b'a', 0, b'z']).wait().err().unwrap();
+        assert_eq!(std::io::ErrorKind::InvalidInput, err.kind());
+        match err.get_ref().unwrap().downcast_ref::<PfcError>() {
+            Some(PfcError::NulByte) => {}
+            _ => panic!("expected a nul byte error"),
+        }
+    }
+
+    #[test]
+    fn add_sorted_unchecked_builds_same_dict() {
+        let blocks = MemoryBackedStore::new();
+        let offsets = MemoryBackedStore::new();
+        let builder = PfcDictFileBuilder::new(blocks.open_write(), offsets.open_write());
+        builder
+            .add_sorted_unchecked("aaa")
+            .and_then(|(_, b)| b.add_sorted_unchecked("aab"))
+            .and_then(|(_, b)| b.add_sorted_unchecked("bbb"))
+            .and_then(|(_, b)| b.finalize())
+            .wait()
+            .unwrap();
+
+        let dict =
+            PfcDict::parse(blocks.map().wait().unwrap(), offsets.map().wait().unwrap()).unwrap();
+        assert_eq!(
+            vec!["aaa", "aab", "bbb"],
+            dict.strings().collect::<Vec<_>>()
+        );
+    }
 }
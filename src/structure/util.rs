@@ -14,6 +14,14 @@ pub fn find_common_prefix(b1: &[u8], b2: &[u8]) -> usize {
     common
 }
 
+/// Returns the amount of bits required to store the given number.
+///
+/// This is computed with integer math, as an f32 `log2` loses
+/// precision above 2^24 and can come out one bit short.
+pub fn calculate_width(size: u64) -> u8 {
+    (64 - size.leading_zeros()) as u8
+}
+
 pub fn write_nul_terminated_bytes<W: tokio::io::AsyncWrite + Send>(
     w: W,
     bytes: Vec<u8>,
@@ -43,3 +51,25 @@ pub fn write_u64<W: tokio::io::AsyncWrite + Send>(
 
     tokio::io::write_all(w, v).map(|(w, _)| w)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calculate_width_of_small_numbers() {
+        assert_eq!(0, calculate_width(0));
+        assert_eq!(1, calculate_width(1));
+        assert_eq!(2, calculate_width(2));
+        assert_eq!(2, calculate_width(3));
+        assert_eq!(3, calculate_width(4));
+        assert_eq!(64, calculate_width(1 << 63));
+    }
+
+    #[test]
+    fn calculate_width_past_f32_precision() {
+        assert_eq!(24, calculate_width(16_777_215));
+        assert_eq!(25, calculate_width(16_777_216));
+        assert_eq!(25, calculate_width(16_777_217));
+    }
+}
@@ -5,8 +5,36 @@ use super::bitindex::*;
 use super::logarray::*;
 use crate::storage::*;
 use futures::prelude::*;
+use std::{error, fmt, io};
 use tokio::prelude::*;
 
+/// An error that occurred while constructing a wavelet tree from its parts.
+#[derive(Debug, PartialEq)]
+pub enum WaveletTreeError {
+    /// the bit index has the given length, which is not a multiple of the given number of layers
+    LayerCountMismatch(usize, u8),
+}
+
+impl fmt::Display for WaveletTreeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WaveletTreeError::LayerCountMismatch(len, num_layers) => write!(
+                f,
+                "expected bit index length ({}) to be a multiple of the number of layers ({})",
+                len, num_layers
+            ),
+        }
+    }
+}
+
+impl error::Error for WaveletTreeError {}
+
+impl From<WaveletTreeError> for io::Error {
+    fn from(err: WaveletTreeError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err)
+    }
+}
+
 /// A wavelet tree, encoding a u64 array for fast lookup of number positions.
 ///
 /// A wavelet tree consists of a layer of bitarrays (stored as one big
@@ -87,11 +115,20 @@ impl<M: AsRef<[u8]>> WaveletLookup<M> {
 impl<M: AsRef<[u8]>> WaveletTree<M> {
     /// Construct a wavelet tree from a bitindex and a layer count.
     pub fn from_parts(bits: BitIndex<M>, num_layers: u8) -> WaveletTree<M> {
+        WaveletTree::try_from_parts(bits, num_layers).unwrap()
+    }
+
+    /// Like `from_parts`, but returns an error instead of panicking
+    /// when the bit index length is not a multiple of the layer count.
+    pub fn try_from_parts(
+        bits: BitIndex<M>,
+        num_layers: u8,
+    ) -> Result<WaveletTree<M>, WaveletTreeError> {
         if num_layers != 0 && bits.len() % num_layers as usize != 0 {
-            panic!("the bitarray length is not a multiple of the number of layers");
+            return Err(WaveletTreeError::LayerCountMismatch(bits.len(), num_layers));
         }
 
-        WaveletTree { bits, num_layers }
+        Ok(WaveletTree { bits, num_layers })
     }
 
     /// Returns the length of the encoded array.
@@ -108,6 +145,11 @@ impl<M: AsRef<[u8]>> WaveletTree<M> {
         self.num_layers as usize
     }
 
+    /// Returns the size in bytes of the underlying bit index.
+    pub fn heap_size(&self) -> usize {
+        self.bits.heap_size()
+    }
+
     /// Decode the wavelet tree to the original u64 sequence. This returns an iterator.
     pub fn decode(&self) -> impl Iterator<Item = u64>
     where
@@ -117,6 +159,13 @@ impl<M: AsRef<[u8]>> WaveletTree<M> {
         (0..self.len()).map(move |i| owned.decode_one(i))
     }
 
+    /// Iterate over the original u64 sequence in position order.
+    ///
+    /// Unlike `decode`, this borrows the tree rather than cloning it.
+    pub fn iter(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len()).map(move |i| self.decode_one(i))
+    }
+
     /// Decode a single position of the original u64 sequence.
     pub fn decode_one(&self, index: usize) -> u64 {
         let len = self.len() as u64;
@@ -158,6 +207,52 @@ impl<M: AsRef<[u8]>> WaveletTree<M> {
         alphabet_start
     }
 
+    /// Returns the amount of positions whose entry is smaller than the given entry.
+    fn count_less_than(&self, entry: u64) -> u64 {
+        let width = self.len() as u64;
+        // with 64 layers, every u64 is inside the alphabet
+        if let Some(alphabet_end) = 1_u64.checked_shl(self.num_layers as u32) {
+            if entry >= alphabet_end {
+                return width;
+            }
+        }
+
+        let mut count = 0;
+        let mut start_index = 0_u64;
+        let mut end_index = width;
+        for i in 0..self.num_layers {
+            if start_index == end_index {
+                break;
+            }
+
+            let full_start_index = (i as u64) * width + start_index;
+            let full_end_index = (i as u64) * width + end_index;
+            let b = entry & (1 << (self.num_layers - i - 1)) != 0;
+            let zeros = self.bits.rank0_from_range(full_start_index, full_end_index);
+            if b {
+                // everything going left at this layer is smaller than entry
+                count += zeros;
+                start_index += zeros;
+            } else {
+                end_index = start_index + zeros;
+            }
+        }
+
+        count
+    }
+
+    /// Returns the amount of positions whose entry lies in the range [lo, hi).
+    ///
+    /// This only needs a rank query per layer for each bound, so it is
+    /// much cheaper than decoding the tree.
+    pub fn range_count(&self, lo: u64, hi: u64) -> u64 {
+        if lo >= hi {
+            0
+        } else {
+            self.count_less_than(hi) - self.count_less_than(lo)
+        }
+    }
+
     /// Lookup the given entry. This returns a `WaveletLookup` which can then be used to find all positions.
     pub fn lookup(&self, entry: u64) -> Option<WaveletLookup<M>>
     where
@@ -253,7 +348,7 @@ pub fn build_wavelet_tree_from_stream<
     destination_sblocks: F,
 ) -> impl Future<Item = (), Error = std::io::Error> + Send {
     let alphabet_size = 2_usize.pow(width as u32);
-    let bits = BitArrayFileBuilder::new(destination_bits.open_write());
+    let bits = BitArrayFileBuilder::new(destination_bits.open_write_truncate());
     stream::iter_ok::<_, std::io::Error>(
         (0..width as usize)
             .map(|layer| (0..2_usize.pow(layer as u32)).map(move |fragment| (layer, fragment)))
@@ -267,8 +362,8 @@ pub fn build_wavelet_tree_from_stream<
     .and_then(move |_| {
         build_bitindex(
             destination_bits.open_read(),
-            destination_blocks.open_write(),
-            destination_sblocks.open_write(),
+            destination_blocks.open_write_truncate(),
+            destination_sblocks.open_write_truncate(),
         )
     })
     .map(|_| ())
@@ -330,6 +425,38 @@ mod tests {
         assert_eq!(contents_len, wavelet_tree.len());
 
         assert_eq!(contents, wavelet_tree.decode().collect::<Vec<_>>());
+        assert_eq!(contents, wavelet_tree.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn try_from_parts_rejects_mismatched_layer_count() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        build_wavelet_tree_from_stream(
+            5,
+            move || stream::iter_ok(contents.clone()),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        )
+        .wait()
+        .unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(
+            wavelet_bits_file.map().wait().unwrap(),
+            wavelet_blocks_file.map().wait().unwrap(),
+            wavelet_sblocks_file.map().wait().unwrap(),
+        );
+
+        assert!(WaveletTree::try_from_parts(wavelet_bitindex.clone(), 5).is_ok());
+        match WaveletTree::try_from_parts(wavelet_bitindex, 7) {
+            Err(WaveletTreeError::LayerCountMismatch(60, 7)) => {}
+            _ => panic!("expected a layer count mismatch"),
+        }
     }
 
     #[test]
@@ -531,4 +658,109 @@ mod tests {
         assert_eq!(Some(7), wavelet_tree.lookup_one(7));
         assert_eq!(Some(4), wavelet_tree.lookup_one(8));
     }
+
+    #[test]
+    fn wavelet_heap_size_covers_all_files() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        build_wavelet_tree_from_stream(
+            5,
+            move || stream::iter_ok(contents.clone()),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        )
+        .wait()
+        .unwrap();
+
+        let expected =
+            wavelet_bits_file.size() + wavelet_blocks_file.size() + wavelet_sblocks_file.size();
+
+        let wavelet_bits = wavelet_bits_file.map().wait().unwrap();
+        let wavelet_blocks = wavelet_blocks_file.map().wait().unwrap();
+        let wavelet_sblocks = wavelet_sblocks_file.map().wait().unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
+
+        assert_eq!(expected, wavelet_tree.heap_size());
+    }
+
+    #[test]
+    fn count_entries_in_range() {
+        let contents = vec![21, 1, 30, 13, 23, 21, 3, 0, 21, 21, 12, 11];
+        let contents_closure = contents.clone();
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+
+        build_wavelet_tree_from_stream(
+            5,
+            move || stream::iter_ok(contents_closure.clone()),
+            wavelet_bits_file.clone(),
+            wavelet_blocks_file.clone(),
+            wavelet_sblocks_file.clone(),
+        )
+        .wait()
+        .unwrap();
+
+        let wavelet_bits = wavelet_bits_file.map().wait().unwrap();
+        let wavelet_blocks = wavelet_blocks_file.map().wait().unwrap();
+        let wavelet_sblocks = wavelet_sblocks_file.map().wait().unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(wavelet_bits, wavelet_blocks, wavelet_sblocks);
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 5);
+
+        assert_eq!(4, wavelet_tree.range_count(21, 22));
+        assert_eq!(12, wavelet_tree.range_count(0, 32));
+        assert_eq!(12, wavelet_tree.range_count(0, 1000));
+        assert_eq!(0, wavelet_tree.range_count(14, 21));
+        assert_eq!(0, wavelet_tree.range_count(20, 3));
+        for lo in 0..34 {
+            for hi in lo..34 {
+                let expected = contents.iter().filter(|&&e| lo <= e && e < hi).count() as u64;
+                assert_eq!(expected, wavelet_tree.range_count(lo, hi));
+            }
+        }
+    }
+
+    #[test]
+    fn count_entries_in_range_with_64_layers() {
+        // with a single entry, every layer holds just the next bit of that entry
+        let entry: u64 = 5;
+        let bits = (0..64).map(move |i| entry & (1 << (63 - i)) != 0);
+
+        let wavelet_bits_file = MemoryBackedStore::new();
+        let wavelet_blocks_file = MemoryBackedStore::new();
+        let wavelet_sblocks_file = MemoryBackedStore::new();
+        BitArrayFileBuilder::new(wavelet_bits_file.open_write())
+            .push_all(stream::iter_ok(bits))
+            .and_then(|b| b.finalize())
+            .and_then(|_| {
+                build_bitindex(
+                    wavelet_bits_file.open_read(),
+                    wavelet_blocks_file.open_write(),
+                    wavelet_sblocks_file.open_write(),
+                )
+            })
+            .wait()
+            .unwrap();
+
+        let wavelet_bitindex = BitIndex::from_maps(
+            wavelet_bits_file.map().wait().unwrap(),
+            wavelet_blocks_file.map().wait().unwrap(),
+            wavelet_sblocks_file.map().wait().unwrap(),
+        );
+        let wavelet_tree = WaveletTree::from_parts(wavelet_bitindex, 64);
+
+        assert_eq!(1, wavelet_tree.range_count(0, 6));
+        assert_eq!(1, wavelet_tree.range_count(5, u64::MAX));
+        assert_eq!(0, wavelet_tree.range_count(0, 5));
+        assert_eq!(0, wavelet_tree.range_count(6, u64::MAX));
+    }
 }